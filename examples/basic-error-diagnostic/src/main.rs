@@ -19,10 +19,7 @@ impl<'a> From<ExecutionError> for Diagnostic<'a> {
             ExecutionError::DatabaseError(err) => ("Retryable", err.to_string()),
             ExecutionError::Unexpected(err) => ("NonRetryable", err.to_string()),
         };
-        Diagnostic {
-            error_type: error_type.into(),
-            error_message: error_message.into(),
-        }
+        Diagnostic::new(error_type, error_message)
     }
 }
 
@@ -0,0 +1,170 @@
+use crate::{
+    custom_serde::{
+        deserialize_headers, deserialize_nullish_boolean, http_method, serialize_headers, serialize_multi_value_headers,
+    },
+    encodings::Body,
+};
+use http::{HeaderMap, Method};
+use query_map::QueryMap;
+use serde::{Deserialize, Serialize};
+
+/// `VpcLatticeRequestV1` contains data originating from a VPC Lattice target group
+/// configured with the event structure version 1.0
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct VpcLatticeRequestV1 {
+    #[serde(default)]
+    pub raw_path: Option<String>,
+    #[serde(with = "http_method")]
+    pub method: Method,
+    #[serde(deserialize_with = "deserialize_headers", default)]
+    #[serde(serialize_with = "serialize_headers")]
+    pub headers: HeaderMap,
+    #[serde(default, deserialize_with = "query_map::serde::standard::deserialize_empty")]
+    pub query_string_parameters: QueryMap,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullish_boolean")]
+    pub is_base64_encoded: bool,
+}
+
+/// `VpcLatticeRequestV2` contains data originating from a VPC Lattice target group
+/// configured with the event structure version 2.0
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcLatticeRequestV2 {
+    /// Version is expected to be `"2.0"`
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(with = "http_method")]
+    pub method: Method,
+    #[serde(deserialize_with = "deserialize_headers", default)]
+    #[serde(serialize_with = "serialize_multi_value_headers")]
+    pub headers: HeaderMap,
+    #[serde(default, deserialize_with = "query_map::serde::standard::deserialize_empty")]
+    pub query_string_parameters: QueryMap,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullish_boolean")]
+    pub is_base64_encoded: bool,
+    pub request_context: VpcLatticeRequestContext,
+}
+
+/// `VpcLatticeRequestContext` contains the information to identify the service and the caller
+/// of a VPC Lattice request
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcLatticeRequestContext {
+    #[serde(default)]
+    pub service_network_arn: Option<String>,
+    #[serde(default)]
+    pub service_arn: Option<String>,
+    #[serde(default)]
+    pub target_group_arn: Option<String>,
+    #[serde(default)]
+    pub identity: VpcLatticeRequestContextIdentity,
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Time of the request in Unix time microseconds
+    #[serde(default)]
+    pub time_epoch: Option<String>,
+}
+
+/// `VpcLatticeRequestContextIdentity` contains the identity of the caller of a VPC Lattice request
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcLatticeRequestContextIdentity {
+    #[serde(default)]
+    pub source_vpc_arn: Option<String>,
+    /// Type of authentication, `AWS_IAM` for requests signed with SigV4
+    #[serde(default, rename = "type")]
+    pub identity_type: Option<String>,
+    #[serde(default)]
+    pub principal: Option<String>,
+    #[serde(default, rename = "principalOrgID")]
+    pub principal_org_id: Option<String>,
+    #[serde(default)]
+    pub session_name: Option<String>,
+    #[serde(default)]
+    pub x509_issuer_ou: Option<String>,
+    #[serde(default)]
+    pub x509_san_dns: Option<String>,
+    #[serde(default)]
+    pub x509_san_name_cn: Option<String>,
+    #[serde(default)]
+    pub x509_san_uri: Option<String>,
+    #[serde(default)]
+    pub x509_subject_cn: Option<String>,
+}
+
+/// `VpcLatticeResponse` configures the response to be returned by VPC Lattice for the request,
+/// for both event structure versions
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcLatticeResponse {
+    pub status_code: i64,
+    #[serde(default)]
+    pub status_description: Option<String>,
+    #[serde(deserialize_with = "http_serde::header_map::deserialize", default)]
+    #[serde(serialize_with = "serialize_headers")]
+    pub headers: HeaderMap,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Body>,
+    #[serde(default, deserialize_with = "deserialize_nullish_boolean")]
+    pub is_base64_encoded: bool,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn example_vpc_lattice_v1_request() {
+        let data = include_bytes!("../../fixtures/example-vpc-lattice-v1-request.json");
+        let parsed: VpcLatticeRequestV1 = serde_json::from_slice(data).unwrap();
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: VpcLatticeRequestV1 = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn example_vpc_lattice_v2_request() {
+        let data = include_bytes!("../../fixtures/example-vpc-lattice-v2-request.json");
+        let parsed: VpcLatticeRequestV2 = serde_json::from_slice(data).unwrap();
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: VpcLatticeRequestV2 = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+        assert_eq!(
+            Some("AWS_IAM"),
+            parsed.request_context.identity.identity_type.as_deref()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn example_vpc_lattice_v2_request_base64() {
+        let data = include_bytes!("../../fixtures/example-vpc-lattice-v2-request-base64.json");
+        let parsed: VpcLatticeRequestV2 = serde_json::from_slice(data).unwrap();
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: VpcLatticeRequestV2 = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+        assert!(parsed.is_base64_encoded);
+        assert_eq!(
+            Body::from_maybe_encoded(parsed.is_base64_encoded, parsed.body.as_deref().unwrap()),
+            Body::Binary(b"hello lattice".to_vec())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn example_vpc_lattice_response() {
+        let data = include_bytes!("../../fixtures/example-vpc-lattice-response.json");
+        let parsed: VpcLatticeResponse = serde_json::from_slice(data).unwrap();
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: VpcLatticeResponse = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+}
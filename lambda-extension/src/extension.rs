@@ -3,8 +3,7 @@ use http_body_util::BodyExt;
 use hyper::{body::Incoming, server::conn::http1, service::service_fn};
 
 use hyper_util::rt::tokio::TokioIo;
-use lambda_runtime_api_client::Client;
-use serde::Deserialize;
+use lambda_runtime_api_client::{api::extension::RegisterResponse, Client};
 use std::{
     convert::Infallible,
     fmt,
@@ -486,24 +485,6 @@ where
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct RegisterResponseBody {
-    function_name: String,
-    function_version: String,
-    handler: String,
-    account_id: Option<String>,
-}
-
-#[derive(Debug)]
-struct RegisterResponse {
-    extension_id: String,
-    function_name: String,
-    function_version: String,
-    handler: String,
-    account_id: Option<String>,
-}
-
 /// Initialize and register the extension in the Extensions API
 async fn register<'a>(
     client: &'a Client,
@@ -532,22 +513,7 @@ async fn register<'a>(
         return Err(ExtensionError::boxed(err));
     }
 
-    let header = res
-        .headers()
-        .get(requests::EXTENSION_ID_HEADER)
-        .ok_or_else(|| ExtensionError::boxed("missing extension id header"))
-        .map_err(|e| ExtensionError::boxed(e.to_string()))?;
-    let extension_id = header.to_str()?.to_string();
-
-    let (_, body) = res.into_parts();
+    let (parts, body) = res.into_parts();
     let body = body.collect().await?.to_bytes();
-    let response: RegisterResponseBody = serde_json::from_slice(&body)?;
-
-    Ok(RegisterResponse {
-        extension_id,
-        function_name: response.function_name,
-        function_version: response.function_version,
-        handler: response.handler,
-        account_id: response.account_id,
-    })
+    Ok(RegisterResponse::from_parts(&parts.headers, &body)?)
 }
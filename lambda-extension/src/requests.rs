@@ -1,40 +1,21 @@
 use crate::{Error, LogBuffering};
-use http::{Method, Request};
-use lambda_runtime_api_client::{body::Body, build_request};
-use serde::Serialize;
+use http::Request;
+use lambda_runtime_api_client::{
+    api::{
+        extension,
+        subscription::{self, SubscribeRequest, SubscriptionApi},
+    },
+    body::Body,
+};
 
-const EXTENSION_NAME_HEADER: &str = "Lambda-Extension-Name";
-pub(crate) const EXTENSION_ID_HEADER: &str = "Lambda-Extension-Identifier";
-const EXTENSION_ERROR_TYPE_HEADER: &str = "Lambda-Extension-Function-Error-Type";
-const CONTENT_TYPE_HEADER_NAME: &str = "Content-Type";
-const CONTENT_TYPE_HEADER_VALUE: &str = "application/json";
-
-// Comma separated list of features the extension supports.
-// `accountId` is currently the only supported feature.
-const EXTENSION_ACCEPT_FEATURE: &str = "Lambda-Extension-Accept-Feature";
-const EXTENSION_ACCEPT_FEATURE_VALUE: &str = "accountId";
+pub use lambda_runtime_api_client::api::extension::ErrorRequest;
 
 pub(crate) fn next_event_request(extension_id: &str) -> Result<Request<Body>, Error> {
-    let req = build_request()
-        .method(Method::GET)
-        .header(EXTENSION_ID_HEADER, extension_id)
-        .uri("/2020-01-01/extension/event/next")
-        .body(Body::empty())?;
-    Ok(req)
+    Ok(extension::next_event(extension_id)?)
 }
 
 pub(crate) fn register_request(extension_name: &str, events: &[&str]) -> Result<Request<Body>, Error> {
-    let events = serde_json::json!({ "events": events });
-
-    let req = build_request()
-        .method(Method::POST)
-        .uri("/2020-01-01/extension/register")
-        .header(EXTENSION_NAME_HEADER, extension_name)
-        .header(EXTENSION_ACCEPT_FEATURE, EXTENSION_ACCEPT_FEATURE_VALUE)
-        .header(CONTENT_TYPE_HEADER_NAME, CONTENT_TYPE_HEADER_VALUE)
-        .body(Body::from(serde_json::to_string(&events)?))?;
-
-    Ok(req)
+    Ok(extension::register(extension_name, events)?)
 }
 
 pub(crate) enum Api {
@@ -42,18 +23,11 @@ pub(crate) enum Api {
     TelemetryApi,
 }
 
-impl Api {
-    pub(crate) fn schema_version(&self) -> &str {
-        match *self {
-            Api::LogsApi => "2021-03-18",
-            Api::TelemetryApi => "2022-07-01",
-        }
-    }
-
-    pub(crate) fn uri(&self) -> &str {
-        match *self {
-            Api::LogsApi => "/2020-08-15/logs",
-            Api::TelemetryApi => "/2022-07-01/telemetry",
+impl From<Api> for SubscriptionApi {
+    fn from(api: Api) -> Self {
+        match api {
+            Api::LogsApi => SubscriptionApi::Logs,
+            Api::TelemetryApi => SubscriptionApi::Telemetry,
         }
     }
 }
@@ -65,38 +39,11 @@ pub(crate) fn subscribe_request(
     buffering: Option<LogBuffering>,
     port_number: u16,
 ) -> Result<Request<Body>, Error> {
+    let api = SubscriptionApi::from(api);
     let types = types.unwrap_or(&["platform", "function"]);
+    let request = SubscribeRequest::new(api, types, buffering.unwrap_or_default(), port_number);
 
-    let data = serde_json::json!({
-        "schemaVersion": api.schema_version(),
-        "types": types,
-        "buffering": buffering.unwrap_or_default(),
-        "destination": {
-            "protocol": "HTTP",
-            "URI": format!("http://sandbox.localdomain:{port_number}"),
-        }
-    });
-
-    let req = build_request()
-        .method(Method::PUT)
-        .uri(api.uri())
-        .header(EXTENSION_ID_HEADER, extension_id)
-        .header(CONTENT_TYPE_HEADER_NAME, CONTENT_TYPE_HEADER_VALUE)
-        .body(Body::from(serde_json::to_string(&data)?))?;
-
-    Ok(req)
-}
-
-/// Payload to send error information to the Extensions API.
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ErrorRequest<'a> {
-    /// Human readable error description
-    pub error_message: &'a str,
-    /// The type of error to categorize
-    pub error_type: &'a str,
-    /// The error backtrace
-    pub stack_trace: Vec<&'a str>,
+    Ok(subscription::subscribe(api, extension_id, &request)?)
 }
 
 /// Create a new init error request to send to the Extensions API
@@ -105,7 +52,7 @@ pub fn init_error(
     error_type: &str,
     request: Option<ErrorRequest<'_>>,
 ) -> Result<Request<Body>, Error> {
-    error_request("init", extension_id, error_type, request)
+    Ok(extension::init_error(extension_id, error_type, request.as_ref())?)
 }
 
 /// Create a new exit error request to send to the Extensions API
@@ -114,27 +61,5 @@ pub fn exit_error(
     error_type: &str,
     request: Option<ErrorRequest<'_>>,
 ) -> Result<Request<Body>, Error> {
-    error_request("exit", extension_id, error_type, request)
-}
-
-fn error_request(
-    error_type: &str,
-    extension_id: &str,
-    error_str: &str,
-    request: Option<ErrorRequest<'_>>,
-) -> Result<Request<Body>, Error> {
-    let uri = format!("/2020-01-01/extension/{error_type}/error");
-
-    let body = match request {
-        None => Body::empty(),
-        Some(err) => Body::from(serde_json::to_string(&err)?),
-    };
-
-    let req = build_request()
-        .method(Method::POST)
-        .uri(uri)
-        .header(EXTENSION_ID_HEADER, extension_id)
-        .header(EXTENSION_ERROR_TYPE_HEADER, error_str)
-        .body(body)?;
-    Ok(req)
+    Ok(extension::exit_error(extension_id, error_type, request.as_ref())?)
 }
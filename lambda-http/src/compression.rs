@@ -0,0 +1,508 @@
+//! Compression of the responses of HTTP functions.
+//!
+//! [CompressionLayer] compresses the body of responses with an encoding accepted by the client,
+//! negotiated with the `Accept-Encoding` header of the request. It works with both
+//! [crate::run], where the compressed body is sent base64 encoded, and
+//! [crate::run_with_streaming_response], where the body is compressed incrementally as it's
+//! streamed to the client.
+//!
+//! Each encoding is enabled with a feature flag: `gzip` and `zstd`.
+use crate::{tower::Layer, Error, Request, Response, Service};
+use bytes::{Buf, Bytes};
+use futures_util::ready;
+use http::{
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, VARY},
+    HeaderMap, HeaderValue,
+};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    io::{self, Write},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Bodies smaller than this size are not worth compressing.
+const MIN_COMPRESSION_SIZE: u64 = 32;
+
+/// Content encodings supported by [CompressionLayer].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// `gzip` encoding.
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// `zstd` encoding.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    /// Supported encodings, in order of preference when the client accepts several of them equally.
+    const PREFERENCE: &'static [Encoding] = &[
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd,
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip,
+    ];
+
+    /// The name of the encoding in the `Content-Encoding` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => "gzip",
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// Choose the preferred encoding accepted by the client, if any.
+    fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+        let accepted = accepted_encodings(headers);
+        let quality = |name: &str| {
+            accepted
+                .iter()
+                .find(|(accepted, _)| accepted.eq_ignore_ascii_case(name))
+                .or_else(|| accepted.iter().find(|(accepted, _)| accepted == "*"))
+                .map(|(_, quality)| *quality)
+                .unwrap_or(0.0)
+        };
+
+        let mut best = None;
+        let mut best_quality = 0.0;
+        for encoding in Self::PREFERENCE {
+            let quality = quality(encoding.as_str());
+            if quality > best_quality {
+                best = Some(*encoding);
+                best_quality = quality;
+            }
+        }
+        best
+    }
+}
+
+/// Parse the `Accept-Encoding` headers into pairs of encoding names and quality values.
+fn accepted_encodings(headers: &HeaderMap) -> Vec<(String, f32)> {
+    headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let name = params.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let quality = params
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map(|quality| quality.trim().parse().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            Some((name.to_string(), quality))
+        })
+        .collect()
+}
+
+/// Tower middleware that compresses the body of HTTP responses.
+///
+/// The encoding is negotiated with the `Accept-Encoding` header of the request. Responses are
+/// left untouched when the client doesn't accept any supported encoding, when they already have
+/// a `Content-Encoding` header, or when their body is known to be too small to benefit from
+/// compression.
+///
+/// # Example
+/// ```no_run
+/// use lambda_http::{
+///     compression::CompressionLayer, run, service_fn, tower::ServiceBuilder, Body, Error, Request, Response,
+/// };
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let service = ServiceBuilder::new()
+///         .layer(CompressionLayer::new())
+///         .service(service_fn(handler));
+///     run(service).await
+/// }
+///
+/// async fn handler(_req: Request) -> Result<Response<Body>, Error> {
+///     Ok(Response::new(Body::from("a".repeat(1024))))
+/// }
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompressionLayer {
+    _priv: (),
+}
+
+impl CompressionLayer {
+    /// Create a new compression layer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for CompressionLayer {
+    type Service = CompressionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CompressionService { inner }
+    }
+}
+
+/// Tower service returned by [CompressionLayer].
+#[derive(Debug, Clone)]
+pub struct CompressionService<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request> for CompressionService<S>
+where
+    S: Service<Request, Response = Response<B>>,
+    B: HttpBody,
+{
+    type Response = Response<CompressionBody<B>>;
+    type Error = S::Error;
+    type Future = CompressionFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let encoding = Encoding::negotiate(req.headers());
+        CompressionFuture {
+            inner: self.inner.call(req),
+            encoding,
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by [CompressionService].
+    pub struct CompressionFuture<F> {
+        #[pin]
+        inner: F,
+        encoding: Option<Encoding>,
+    }
+}
+
+impl<F, B, E> Future for CompressionFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: HttpBody,
+{
+    type Output = Result<Response<CompressionBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let response = ready!(this.inner.poll(cx))?;
+        Poll::Ready(Ok(compress(response, *this.encoding)))
+    }
+}
+
+fn compress<B>(response: Response<B>, encoding: Option<Encoding>) -> Response<CompressionBody<B>>
+where
+    B: HttpBody,
+{
+    let (mut parts, body) = response.into_parts();
+    if !varies_on_accept_encoding(&parts.headers) {
+        parts.headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    }
+
+    let too_small = body.size_hint().exact().is_some_and(|size| size < MIN_COMPRESSION_SIZE);
+    let encoder = encoding
+        .filter(|_| !too_small && !parts.headers.contains_key(CONTENT_ENCODING))
+        .and_then(|encoding| Encoder::new(encoding).ok().map(|encoder| (encoding, encoder)));
+
+    let encoder = match encoder {
+        Some((encoding, encoder)) => {
+            parts
+                .headers
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+            parts.headers.remove(CONTENT_LENGTH);
+            Some(encoder)
+        }
+        None => None,
+    };
+
+    let body = CompressionBody {
+        inner: body,
+        encoder,
+        trailers: None,
+        done: false,
+    };
+    Response::from_parts(parts, body)
+}
+
+/// Whether the `Vary` header of a response already covers the `Accept-Encoding` header.
+fn varies_on_accept_encoding(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|name| name == "*" || name.eq_ignore_ascii_case(ACCEPT_ENCODING.as_str()))
+}
+
+/// Incremental encoder of a response body.
+enum Encoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding) -> io::Result<Self> {
+        match encoding {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => Ok(Encoder::Gzip(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            ))),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => Ok(Encoder::Zstd(zstd::stream::write::Encoder::new(
+                Vec::new(),
+                zstd::DEFAULT_COMPRESSION_LEVEL,
+            )?)),
+        }
+    }
+
+    /// Compress a chunk of the body, and return the compressed data available so far.
+    ///
+    /// The encoder is flushed after every chunk, so streamed responses reach the client
+    /// as they are produced instead of when the compression buffers fill up.
+    fn encode(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()).into())
+            }
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()).into())
+            }
+        }
+    }
+
+    /// Finish the compressed stream, and return the remaining compressed data.
+    fn finish(self) -> io::Result<Bytes> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => Ok(encoder.finish()?.into()),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => Ok(encoder.finish()?.into()),
+        }
+    }
+}
+
+pin_project! {
+    /// Response body compressed by [CompressionService].
+    pub struct CompressionBody<B> {
+        #[pin]
+        inner: B,
+        encoder: Option<Encoder>,
+        trailers: Option<HeaderMap>,
+        done: bool,
+    }
+}
+
+impl<B> HttpBody for CompressionBody<B>
+where
+    B: HttpBody,
+    B::Error: Into<Error>,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            if *this.done {
+                return Poll::Ready(this.trailers.take().map(|trailers| Ok(Frame::trailers(trailers))));
+            }
+
+            let Some(encoder) = this.encoder.as_mut() else {
+                let frame = ready!(this.inner.as_mut().poll_frame(cx));
+                let frame = frame.map(|frame| {
+                    frame
+                        .map(|frame| frame.map_data(|mut data| data.copy_to_bytes(data.remaining())))
+                        .map_err(Into::into)
+                });
+                return Poll::Ready(frame);
+            };
+
+            let chunk = match ready!(this.inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(mut data) => encoder.encode(&data.copy_to_bytes(data.remaining())),
+                    Err(frame) => {
+                        *this.trailers = frame.into_trailers().ok();
+                        *this.done = true;
+                        this.encoder.take().expect("the encoder is only taken once").finish()
+                    }
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => {
+                    *this.done = true;
+                    this.encoder.take().expect("the encoder is only taken once").finish()
+                }
+            };
+
+            match chunk {
+                Ok(chunk) if chunk.is_empty() => continue,
+                Ok(chunk) => return Poll::Ready(Some(Ok(Frame::data(chunk)))),
+                Err(err) => return Poll::Ready(Some(Err(err.into()))),
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        if self.encoder.is_none() && !self.done {
+            self.inner.is_end_stream()
+        } else {
+            self.done && self.trailers.is_none()
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        if self.encoder.is_none() && !self.done {
+            self.inner.size_hint()
+        } else {
+            SizeHint::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, tower::ServiceExt, Body};
+    use http_body_util::{BodyExt, StreamBody};
+    use std::{convert::Infallible, io::Read};
+
+    const PAYLOAD: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor.";
+
+    fn request(accept_encoding: &str) -> Request {
+        http::Request::builder()
+            .header(ACCEPT_ENCODING, accept_encoding)
+            .body(Body::Empty)
+            .unwrap()
+    }
+
+    async fn call(req: Request, body: &'static str) -> Response<CompressionBody<Body>> {
+        let service = service_fn(move |_: Request| async move { Ok::<_, Infallible>(Response::new(Body::from(body))) });
+        CompressionLayer::new().layer(service).oneshot(req).await.unwrap()
+    }
+
+    #[test]
+    fn negotiate_no_encoding() {
+        assert_eq!(None, Encoding::negotiate(&HeaderMap::new()));
+        assert_eq!(None, Encoding::negotiate(request("br, identity").headers()));
+        assert_eq!(None, Encoding::negotiate(request("*;q=0").headers()));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn negotiate_gzip() {
+        assert_eq!(Some(Encoding::Gzip), Encoding::negotiate(request("gzip").headers()));
+        assert_eq!(
+            Some(Encoding::Gzip),
+            Encoding::negotiate(request("zstd;q=0.5, GZIP;q=0.8").headers())
+        );
+    }
+
+    #[cfg(all(feature = "gzip", feature = "zstd"))]
+    #[test]
+    fn negotiate_prefers_zstd_on_ties() {
+        assert_eq!(
+            Some(Encoding::Zstd),
+            Encoding::negotiate(request("gzip, zstd").headers())
+        );
+        assert_eq!(Some(Encoding::Zstd), Encoding::negotiate(request("*").headers()));
+    }
+
+    #[tokio::test]
+    async fn response_without_accepted_encoding_is_not_compressed() {
+        let res = call(request("identity"), PAYLOAD).await;
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!("accept-encoding", res.headers()[VARY]);
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(PAYLOAD.as_bytes(), body);
+    }
+
+    #[test]
+    fn vary_header_is_not_duplicated() {
+        let response = Response::builder()
+            .header(VARY, "Origin, Accept-Encoding")
+            .body(Body::from(PAYLOAD))
+            .unwrap();
+        let res = compress(response, None);
+        let vary: Vec<_> = res.headers().get_all(VARY).iter().collect();
+        assert_eq!(vec!["Origin, Accept-Encoding"], vary);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn small_response_is_not_compressed() {
+        let res = call(request("gzip"), "ok").await;
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn buffered_response_is_compressed_with_gzip() {
+        let res = call(request("gzip"), PAYLOAD).await;
+        assert_eq!("gzip", res.headers()[CONTENT_ENCODING]);
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(PAYLOAD, decoded);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn buffered_response_is_compressed_with_zstd() {
+        let res = call(request("zstd"), PAYLOAD).await;
+        assert_eq!("zstd", res.headers()[CONTENT_ENCODING]);
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let decoded = zstd::decode_all(&body[..]).unwrap();
+        assert_eq!(PAYLOAD.as_bytes(), decoded);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn streamed_response_is_compressed_incrementally() {
+        let chunks = ["first chunk, ", "second chunk, ", "third chunk"]
+            .into_iter()
+            .map(|chunk| Ok::<_, Infallible>(Frame::data(Bytes::from_static(chunk.as_bytes()))));
+        let body = StreamBody::new(futures::stream::iter(chunks));
+        let res = compress(Response::new(body), Some(Encoding::Gzip));
+        assert_eq!("gzip", res.headers()[CONTENT_ENCODING]);
+
+        let mut body = res.into_body();
+        let mut frames = 0;
+        let mut compressed = Vec::new();
+        while let Some(frame) = body.frame().await {
+            compressed.extend_from_slice(&frame.unwrap().into_data().unwrap());
+            frames += 1;
+        }
+        // One frame per chunk, plus the end of the gzip stream.
+        assert_eq!(4, frames);
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&compressed[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!("first chunk, second chunk, third chunk", decoded);
+    }
+}
@@ -7,6 +7,10 @@ use aws_lambda_events::apigw::ApiGatewayProxyRequest;
 use aws_lambda_events::apigw::ApiGatewayV2httpRequest;
 #[cfg(feature = "apigw_websockets")]
 use aws_lambda_events::apigw::ApiGatewayWebsocketProxyRequest;
+#[cfg(feature = "function_url")]
+use aws_lambda_events::lambda_function_urls::LambdaFunctionUrlRequest;
+#[cfg(feature = "vpc_lattice")]
+use aws_lambda_events::vpc_lattice::{VpcLatticeRequestV1, VpcLatticeRequestV2};
 use serde::{de::Error, Deserialize};
 use serde_json::value::RawValue;
 
@@ -27,6 +31,13 @@ impl<'de> Deserialize<'de> for LambdaRequest {
         if let Ok(res) = serde_json::from_str::<ApiGatewayProxyRequest>(data) {
             return Ok(LambdaRequest::ApiGatewayV1(res));
         }
+        // Function URL events conform to the API GW v2 format, so they are told apart by their domain.
+        #[cfg(feature = "function_url")]
+        if let Ok(res) = serde_json::from_str::<LambdaFunctionUrlRequest>(data) {
+            if is_function_url(&res) {
+                return Ok(LambdaRequest::LambdaFunctionUrl(res));
+            }
+        }
         #[cfg(feature = "apigw_http")]
         if let Ok(res) = serde_json::from_str::<ApiGatewayV2httpRequest>(data) {
             return Ok(LambdaRequest::ApiGatewayV2(res));
@@ -39,6 +50,18 @@ impl<'de> Deserialize<'de> for LambdaRequest {
         if let Ok(res) = serde_json::from_str::<ApiGatewayWebsocketProxyRequest>(data) {
             return Ok(LambdaRequest::WebSocket(res));
         }
+        // Only VPC Lattice events have a top-level `method`. The version 2.0 must be tried first,
+        // because its payloads also match the structure of the version 1.0.
+        #[cfg(feature = "vpc_lattice")]
+        if let Ok(res) = serde_json::from_str::<VpcLatticeRequestV2>(data) {
+            return Ok(LambdaRequest::VpcLatticeV2(res));
+        }
+        #[cfg(feature = "vpc_lattice")]
+        if let Ok(res) = serde_json::from_str::<VpcLatticeRequestV1>(data) {
+            if res.raw_path.is_some() {
+                return Ok(LambdaRequest::VpcLatticeV1(res));
+            }
+        }
         #[cfg(feature = "pass_through")]
         if PASS_THROUGH_ENABLED {
             return Ok(LambdaRequest::PassThrough(data.to_string()));
@@ -48,6 +71,15 @@ impl<'de> Deserialize<'de> for LambdaRequest {
     }
 }
 
+/// Lambda Function URLs have domains like `<url-id>.lambda-url.<region>.on.aws`.
+#[cfg(feature = "function_url")]
+fn is_function_url(req: &LambdaFunctionUrlRequest) -> bool {
+    req.request_context
+        .domain_name
+        .as_deref()
+        .is_some_and(|domain| domain.contains(".lambda-url."))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,6 +110,51 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(feature = "function_url")]
+    fn test_deserialize_function_url() {
+        let data = include_bytes!("../tests/data/lambda_function_url_request.json");
+
+        let req: LambdaRequest = serde_json::from_slice(data).expect("failed to deserialize function url data");
+        match req {
+            LambdaRequest::LambdaFunctionUrl(req) => {
+                assert_eq!("123456789012", req.request_context.account_id.unwrap());
+            }
+            other => panic!("unexpected request variant: {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn test_deserialize_vpc_lattice_v1() {
+        let data = include_bytes!("../../lambda-events/src/fixtures/example-vpc-lattice-v1-request.json");
+
+        let req: LambdaRequest = serde_json::from_slice(data).expect("failed to deserialize vpc lattice v1 data");
+        match req {
+            LambdaRequest::VpcLatticeV1(req) => {
+                assert_eq!("/todos", req.raw_path.unwrap());
+            }
+            other => panic!("unexpected request variant: {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn test_deserialize_vpc_lattice_v2() {
+        let data = include_bytes!("../../lambda-events/src/fixtures/example-vpc-lattice-v2-request.json");
+
+        let req: LambdaRequest = serde_json::from_slice(data).expect("failed to deserialize vpc lattice v2 data");
+        match req {
+            LambdaRequest::VpcLatticeV2(req) => {
+                assert_eq!(
+                    "arn:aws:vpc-lattice:us-east-1:123456789012:service/svc-0a40eebed65f8d69c",
+                    req.request_context.service_arn.unwrap()
+                );
+            }
+            other => panic!("unexpected request variant: {:?}", other),
+        }
+    }
+
     #[test]
     fn test_deserialize_sam_rest() {
         let data = include_bytes!("../../lambda-events/src/fixtures/example-apigw-sam-rest-request.json");
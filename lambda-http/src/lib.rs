@@ -73,6 +73,8 @@ pub use lambda_runtime::{self, service_fn, tower, Context, Error, LambdaEvent, S
 use request::RequestFuture;
 use response::ResponseFuture;
 
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub mod compression;
 mod deserializer;
 pub mod ext;
 pub mod request;
@@ -100,7 +102,7 @@ use std::{
 };
 
 mod streaming;
-pub use streaming::run_with_streaming_response;
+pub use streaming::{run_with_streaming_response, BodyStream, StreamAdapter};
 
 /// Type alias for `http::Request`s with a fixed [`Body`](enum.Body.html) type
 pub type Request = http::Request<Body>;
@@ -12,7 +12,9 @@ use crate::ext::extensions::{PathParameters, StageVariables};
     feature = "apigw_rest",
     feature = "apigw_http",
     feature = "alb",
-    feature = "apigw_websockets"
+    feature = "apigw_websockets",
+    feature = "function_url",
+    feature = "vpc_lattice"
 ))]
 use crate::ext::extensions::{QueryStringParameters, RawHttpPath};
 #[cfg(feature = "alb")]
@@ -25,6 +27,12 @@ use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyRequestCon
 use aws_lambda_events::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpRequestContext};
 #[cfg(feature = "apigw_websockets")]
 use aws_lambda_events::apigw::{ApiGatewayWebsocketProxyRequest, ApiGatewayWebsocketProxyRequestContext};
+#[cfg(feature = "function_url")]
+use aws_lambda_events::lambda_function_urls::{
+    LambdaFunctionUrlRequest, LambdaFunctionUrlRequestContext, LambdaFunctionUrlRequestContextAuthorizerIamDescription,
+};
+#[cfg(feature = "vpc_lattice")]
+use aws_lambda_events::vpc_lattice::{VpcLatticeRequestContext, VpcLatticeRequestV1, VpcLatticeRequestV2};
 use aws_lambda_events::{encodings::Body, query_map::QueryMap};
 use http::{header::HeaderName, HeaderMap, HeaderValue};
 
@@ -50,6 +58,12 @@ pub enum LambdaRequest {
     Alb(AlbTargetGroupRequest),
     #[cfg(feature = "apigw_websockets")]
     WebSocket(ApiGatewayWebsocketProxyRequest),
+    #[cfg(feature = "function_url")]
+    LambdaFunctionUrl(LambdaFunctionUrlRequest),
+    #[cfg(feature = "vpc_lattice")]
+    VpcLatticeV1(VpcLatticeRequestV1),
+    #[cfg(feature = "vpc_lattice")]
+    VpcLatticeV2(VpcLatticeRequestV2),
     #[cfg(feature = "pass_through")]
     PassThrough(String),
 }
@@ -68,15 +82,21 @@ impl LambdaRequest {
             LambdaRequest::Alb { .. } => RequestOrigin::Alb,
             #[cfg(feature = "apigw_websockets")]
             LambdaRequest::WebSocket { .. } => RequestOrigin::WebSocket,
+            #[cfg(feature = "function_url")]
+            LambdaRequest::LambdaFunctionUrl { .. } => RequestOrigin::LambdaFunctionUrl,
+            #[cfg(feature = "vpc_lattice")]
+            LambdaRequest::VpcLatticeV1 { .. } | LambdaRequest::VpcLatticeV2 { .. } => RequestOrigin::VpcLattice,
             #[cfg(feature = "pass_through")]
             LambdaRequest::PassThrough { .. } => RequestOrigin::PassThrough,
             #[cfg(not(any(
                 feature = "apigw_rest",
                 feature = "apigw_http",
                 feature = "alb",
-                feature = "apigw_websockets"
+                feature = "apigw_websockets",
+                feature = "function_url",
+                feature = "vpc_lattice"
             )))]
-            _ => compile_error!("Either feature `apigw_rest`, `apigw_http`, `alb`, or `apigw_websockets` must be enabled for the `lambda-http` crate."),
+            _ => compile_error!("Either feature `apigw_rest`, `apigw_http`, `alb`, `apigw_websockets`, `function_url`, or `vpc_lattice` must be enabled for the `lambda-http` crate."),
         }
     }
 }
@@ -100,6 +120,16 @@ pub enum RequestOrigin {
     /// API Gateway WebSocket
     #[cfg(feature = "apigw_websockets")]
     WebSocket,
+    /// Lambda Function URL request origin
+    ///
+    /// Function URL events have the same shape as API Gateway v2 events, so they are
+    /// recognized by their `<url-id>.lambda-url.<region>.on.aws` domain. Function URLs
+    /// served behind a custom domain or CloudFront are reported as `ApiGatewayV2` instead.
+    #[cfg(feature = "function_url")]
+    LambdaFunctionUrl,
+    /// VPC Lattice request origin, for both event structure versions
+    #[cfg(feature = "vpc_lattice")]
+    VpcLattice,
     /// PassThrough request origin
     #[cfg(feature = "pass_through")]
     PassThrough,
@@ -107,53 +137,116 @@ pub enum RequestOrigin {
 
 #[cfg(feature = "apigw_http")]
 fn into_api_gateway_v2_request(ag: ApiGatewayV2httpRequest) -> http::Request<Body> {
-    let http_method = ag.request_context.http.method.clone();
-    let host = ag
+    let raw_path = ag.raw_path.unwrap_or_default();
+    let path = apigw_path_with_stage(&ag.request_context.stage, &raw_path);
+
+    let mut req = into_http_payload_v2_request(HttpPayloadV2Parts {
+        method: ag.request_context.http.method.clone(),
+        path,
+        raw_path,
+        domain_name: ag.request_context.domain_name.clone(),
+        headers: ag.headers,
+        cookies: ag.cookies,
+        raw_query_string: ag.raw_query_string,
+        query_string_parameters: ag.query_string_parameters,
+        body: ag.body,
+        is_base64_encoded: ag.is_base64_encoded,
+        request_context: RequestContext::ApiGatewayV2(ag.request_context),
+    });
+    req.extensions_mut()
+        .insert(PathParameters(QueryMap::from(ag.path_parameters)));
+    req.extensions_mut()
+        .insert(StageVariables(QueryMap::from(ag.stage_variables)));
+    req
+}
+
+#[cfg(feature = "function_url")]
+fn into_function_url_request(fu: LambdaFunctionUrlRequest) -> http::Request<Body> {
+    let raw_path = fu.raw_path.unwrap_or_default();
+
+    into_http_payload_v2_request(HttpPayloadV2Parts {
+        method: fu
+            .request_context
+            .http
+            .method
+            .as_deref()
+            .and_then(|m| m.parse().ok())
+            .unwrap_or_default(),
+        path: raw_path.clone(),
+        raw_path,
+        domain_name: fu.request_context.domain_name.clone(),
+        headers: fu.headers,
+        cookies: fu.cookies,
+        raw_query_string: fu.raw_query_string,
+        query_string_parameters: QueryMap::from(fu.query_string_parameters),
+        body: fu.body,
+        is_base64_encoded: fu.is_base64_encoded,
+        request_context: RequestContext::LambdaFunctionUrl(fu.request_context),
+    })
+}
+
+/// The parts shared by the events of API Gateway HTTP APIs and Lambda Function URLs,
+/// which both use the version 2.0 of the payload format.
+#[cfg(any(feature = "apigw_http", feature = "function_url"))]
+struct HttpPayloadV2Parts {
+    method: http::Method,
+    path: String,
+    raw_path: String,
+    domain_name: Option<String>,
+    headers: HeaderMap,
+    cookies: Option<Vec<String>>,
+    raw_query_string: Option<String>,
+    query_string_parameters: QueryMap,
+    body: Option<String>,
+    is_base64_encoded: bool,
+    request_context: RequestContext,
+}
+
+#[cfg(any(feature = "apigw_http", feature = "function_url"))]
+fn into_http_payload_v2_request(parts: HttpPayloadV2Parts) -> http::Request<Body> {
+    let host = parts
         .headers
         .get(http::header::HOST)
         .and_then(|s| s.to_str().ok())
-        .or(ag.request_context.domain_name.as_deref());
-    let raw_path = ag.raw_path.unwrap_or_default();
-    let path = apigw_path_with_stage(&ag.request_context.stage, &raw_path);
+        .or(parts.domain_name.as_deref());
 
     // don't use the query_string_parameters from API GW v2 to
     // populate the QueryStringParameters extension because
     // the value is not compatible with the whatgw specification.
     // See: https://github.com/awslabs/aws-lambda-rust-runtime/issues/470
     // See: https://url.spec.whatwg.org/#urlencoded-parsing
-    let query_string_parameters = if let Some(query) = &ag.raw_query_string {
+    let query_string_parameters = if let Some(query) = &parts.raw_query_string {
         query.parse().unwrap() // this is Infallible
     } else {
-        ag.query_string_parameters
+        parts.query_string_parameters
     };
 
-    let mut uri = build_request_uri(&path, &ag.headers, host, None);
-    if let Some(query) = ag.raw_query_string {
+    let mut uri = build_request_uri(&parts.path, &parts.headers, host, None);
+    if let Some(query) = parts.raw_query_string {
         uri.push('?');
         uri.push_str(&query);
     }
 
     let builder = http::Request::builder()
         .uri(uri)
-        .extension(RawHttpPath(raw_path))
+        .extension(RawHttpPath(parts.raw_path))
         .extension(QueryStringParameters(query_string_parameters))
-        .extension(PathParameters(QueryMap::from(ag.path_parameters)))
-        .extension(StageVariables(QueryMap::from(ag.stage_variables)))
-        .extension(RequestContext::ApiGatewayV2(ag.request_context));
+        .extension(parts.request_context);
 
-    let mut headers = ag.headers;
+    let mut headers = parts.headers;
     update_xray_trace_id_header(&mut headers);
-    if let Some(cookies) = ag.cookies {
+    if let Some(cookies) = parts.cookies {
         if let Ok(header_value) = HeaderValue::from_str(&cookies.join(";")) {
             headers.insert(http::header::COOKIE, header_value);
         }
     }
 
-    let base64 = ag.is_base64_encoded;
+    let base64 = parts.is_base64_encoded;
 
     let mut req = builder
         .body(
-            ag.body
+            parts
+                .body
                 .as_deref()
                 .map_or_else(Body::default, |b| Body::from_maybe_encoded(base64, b)),
         )
@@ -161,7 +254,91 @@ fn into_api_gateway_v2_request(ag: ApiGatewayV2httpRequest) -> http::Request<Bod
 
     // no builder method that sets headers in batch
     let _ = std::mem::replace(req.headers_mut(), headers);
-    let _ = std::mem::replace(req.method_mut(), http_method);
+    let _ = std::mem::replace(req.method_mut(), parts.method);
+
+    req
+}
+
+#[cfg(feature = "vpc_lattice")]
+fn into_vpc_lattice_v1_request(lattice: VpcLatticeRequestV1) -> http::Request<Body> {
+    into_vpc_lattice_request(VpcLatticeParts {
+        method: lattice.method,
+        path: lattice.raw_path.unwrap_or_default(),
+        headers: lattice.headers,
+        query_string_parameters: lattice.query_string_parameters,
+        body: lattice.body,
+        is_base64_encoded: lattice.is_base64_encoded,
+        // The version 1.0 of the event structure has no request context.
+        request_context: VpcLatticeRequestContext::default(),
+    })
+}
+
+#[cfg(feature = "vpc_lattice")]
+fn into_vpc_lattice_v2_request(lattice: VpcLatticeRequestV2) -> http::Request<Body> {
+    into_vpc_lattice_request(VpcLatticeParts {
+        method: lattice.method,
+        path: lattice.path.unwrap_or_default(),
+        headers: lattice.headers,
+        query_string_parameters: lattice.query_string_parameters,
+        body: lattice.body,
+        is_base64_encoded: lattice.is_base64_encoded,
+        request_context: lattice.request_context,
+    })
+}
+
+/// The parts shared by both versions of the VPC Lattice event structure.
+#[cfg(feature = "vpc_lattice")]
+struct VpcLatticeParts {
+    method: http::Method,
+    path: String,
+    headers: HeaderMap,
+    query_string_parameters: QueryMap,
+    body: Option<String>,
+    is_base64_encoded: bool,
+    request_context: VpcLatticeRequestContext,
+}
+
+#[cfg(feature = "vpc_lattice")]
+fn into_vpc_lattice_request(lattice: VpcLatticeParts) -> http::Request<Body> {
+    let host = lattice.headers.get(http::header::HOST).and_then(|s| s.to_str().ok());
+
+    // The path can include the query string, in which case it's used as is.
+    let (raw_path, uri) = match lattice.path.split_once('?') {
+        Some((path, _)) => (
+            path.to_string(),
+            build_request_uri(&lattice.path, &lattice.headers, host, None),
+        ),
+        None => {
+            let no_query = QueryMap::default();
+            let queries = Some((&lattice.query_string_parameters, &no_query));
+            let uri = build_request_uri(&lattice.path, &lattice.headers, host, queries);
+            (lattice.path, uri)
+        }
+    };
+
+    let builder = http::Request::builder()
+        .uri(uri)
+        .extension(RawHttpPath(raw_path))
+        .extension(QueryStringParameters(lattice.query_string_parameters))
+        .extension(RequestContext::VpcLattice(lattice.request_context));
+
+    let mut headers = lattice.headers;
+    update_xray_trace_id_header(&mut headers);
+
+    let base64 = lattice.is_base64_encoded;
+
+    let mut req = builder
+        .body(
+            lattice
+                .body
+                .as_deref()
+                .map_or_else(Body::default, |b| Body::from_maybe_encoded(base64, b)),
+        )
+        .expect("failed to build request");
+
+    // no builder method that sets headers in batch
+    let _ = std::mem::replace(req.headers_mut(), headers);
+    let _ = std::mem::replace(req.method_mut(), lattice.method);
 
     req
 }
@@ -401,6 +578,12 @@ pub enum RequestContext {
     /// WebSocket request context
     #[cfg(feature = "apigw_websockets")]
     WebSocket(ApiGatewayWebsocketProxyRequestContext),
+    /// Lambda Function URL request context
+    #[cfg(feature = "function_url")]
+    LambdaFunctionUrl(LambdaFunctionUrlRequestContext),
+    /// VPC Lattice request context, empty for the version 1.0 of the event structure
+    #[cfg(feature = "vpc_lattice")]
+    VpcLattice(VpcLatticeRequestContext),
     /// Custom request context
     #[cfg(feature = "pass_through")]
     PassThrough,
@@ -418,6 +601,12 @@ impl From<LambdaRequest> for http::Request<Body> {
             LambdaRequest::Alb(alb) => into_alb_request(alb),
             #[cfg(feature = "apigw_websockets")]
             LambdaRequest::WebSocket(ag) => into_websocket_request(ag),
+            #[cfg(feature = "function_url")]
+            LambdaRequest::LambdaFunctionUrl(fu) => into_function_url_request(fu),
+            #[cfg(feature = "vpc_lattice")]
+            LambdaRequest::VpcLatticeV1(lattice) => into_vpc_lattice_v1_request(lattice),
+            #[cfg(feature = "vpc_lattice")]
+            LambdaRequest::VpcLatticeV2(lattice) => into_vpc_lattice_v2_request(lattice),
             #[cfg(feature = "pass_through")]
             LambdaRequest::PassThrough(data) => into_pass_through_request(data),
         }
@@ -435,7 +624,22 @@ impl RequestContext {
             Self::ApiGatewayV2(ag) => ag.authorizer.as_ref(),
             #[cfg(feature = "apigw_websockets")]
             Self::WebSocket(ag) => Some(&ag.authorizer),
-            #[cfg(any(feature = "alb", feature = "pass_through"))]
+            #[cfg(any(
+                feature = "alb",
+                feature = "function_url",
+                feature = "vpc_lattice",
+                feature = "pass_through"
+            ))]
+            _ => None,
+        }
+    }
+
+    /// Returns the IAM identity of the caller of a Lambda Function URL that uses `AWS_IAM` auth.
+    #[cfg(feature = "function_url")]
+    pub fn function_url_iam(&self) -> Option<&LambdaFunctionUrlRequestContextAuthorizerIamDescription> {
+        match self {
+            Self::LambdaFunctionUrl(fu) => fu.authorizer.as_ref()?.iam.as_ref(),
+            #[allow(unreachable_patterns)]
             _ => None,
         }
     }
@@ -642,12 +846,43 @@ mod tests {
         );
         assert_eq!(cookie_header, Some("test=hi".to_string()));
 
-        // Ensure this is an APIGWv2 request (Lambda Function URL requests confirm to API GW v2 Request format)
+        // Without the `function_url` feature, Lambda Function URL requests are handled as
+        // APIGWv2 requests, because they conform to the API GW v2 Request format
         let req_context = req.request_context_ref().expect("Request is missing RequestContext");
+        #[cfg(not(feature = "function_url"))]
         assert!(
             matches!(req_context, &RequestContext::ApiGatewayV2(_)),
             "expected ApiGatewayV2 context, got {req_context:?}"
         );
+        #[cfg(feature = "function_url")]
+        assert!(
+            matches!(req_context, &RequestContext::LambdaFunctionUrl(_)),
+            "expected LambdaFunctionUrl context, got {req_context:?}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "function_url")]
+    fn deserializes_lambda_function_url_iam_request_events() {
+        let input = include_str!("../tests/data/lambda_function_url_iam_request.json");
+        let req = from_str(input).expect("failed to parse request");
+
+        assert_eq!(req.method(), "POST");
+        assert_eq!(req.uri(), "https://id.lambda-url.us-east-1.on.aws/orders");
+        assert_eq!(req.raw_http_path(), "/orders");
+        assert_eq!(req.body(), &Body::Text("{\"id\":42}".into()));
+
+        let req_context = req.request_context();
+        assert!(
+            matches!(req_context, RequestContext::LambdaFunctionUrl(_)),
+            "expected LambdaFunctionUrl context, got {req_context:?}"
+        );
+        let iam = req_context.function_url_iam().expect("missing IAM auth info");
+        assert_eq!(iam.account_id.as_deref(), Some("111122223333"));
+        assert_eq!(
+            iam.user_arn.as_deref(),
+            Some("arn:aws:iam::111122223333:user/example-user")
+        );
     }
 
     #[test]
@@ -675,6 +910,58 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn deserializes_vpc_lattice_v1_request_events() {
+        let input = include_str!("../tests/data/vpc_lattice_v1_request.json");
+        let result = from_str(input);
+        assert!(
+            result.is_ok(),
+            "event was not parsed as expected {result:?} given {input}"
+        );
+        let req = result.expect("failed to parse request");
+        assert_eq!(req.method(), "GET");
+        assert_eq!(
+            req.uri(),
+            "https://todos-0a8e1f29b3c4d5e6f.7d67968.vpc-lattice-svcs.us-east-1.on.aws/todos?completed=false"
+        );
+        assert_eq!(req.raw_http_path(), "/todos");
+        assert_eq!(
+            req.query_string_parameters_ref().and_then(|q| q.first("completed")),
+            Some("false")
+        );
+
+        // Ensure this is a VPC Lattice request
+        let req_context = req.request_context_ref().expect("Request is missing RequestContext");
+        assert!(
+            matches!(req_context, &RequestContext::VpcLattice(_)),
+            "expected VpcLattice context, got {req_context:?}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn deserializes_vpc_lattice_v2_base64_request_events() {
+        let input = include_str!("../tests/data/vpc_lattice_v2_request_base64.json");
+        let result = from_str(input);
+        assert!(
+            result.is_ok(),
+            "event was not parsed as expected {result:?} given {input}"
+        );
+        let req = result.expect("failed to parse request");
+        assert_eq!(req.method(), "PUT");
+        assert_eq!(
+            req.uri(),
+            "https://uploads-0a8e1f29b3c4d5e6f.7d67968.vpc-lattice-svcs.us-east-1.on.aws/upload"
+        );
+        assert_eq!(req.body(), &Body::Binary(b"hello lattice".to_vec()));
+
+        match req.request_context() {
+            RequestContext::VpcLattice(ctx) => assert_eq!(ctx.region.as_deref(), Some("us-east-1")),
+            other => panic!("expected VpcLattice context, got {other:?}"),
+        }
+    }
+
     #[test]
     fn deserializes_alb_request_encoded_query_parameters_events() {
         // from the docs
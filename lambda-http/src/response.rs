@@ -8,6 +8,10 @@ use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 #[cfg(feature = "apigw_http")]
 use aws_lambda_events::apigw::ApiGatewayV2httpResponse;
 use aws_lambda_events::encodings::Body;
+#[cfg(feature = "function_url")]
+use aws_lambda_events::lambda_function_urls::LambdaFunctionUrlResponse;
+#[cfg(feature = "vpc_lattice")]
+use aws_lambda_events::vpc_lattice::VpcLatticeResponse;
 use encoding_rs::Encoding;
 use http::{
     header::{CONTENT_ENCODING, CONTENT_TYPE},
@@ -50,6 +54,10 @@ pub enum LambdaResponse {
     ApiGatewayV2(ApiGatewayV2httpResponse),
     #[cfg(feature = "alb")]
     Alb(AlbTargetGroupResponse),
+    #[cfg(feature = "function_url")]
+    LambdaFunctionUrl(LambdaFunctionUrlResponse),
+    #[cfg(feature = "vpc_lattice")]
+    VpcLattice(VpcLatticeResponse),
     #[cfg(feature = "pass_through")]
     PassThrough(serde_json::Value),
 }
@@ -80,17 +88,10 @@ impl LambdaResponse {
             }),
             #[cfg(feature = "apigw_http")]
             RequestOrigin::ApiGatewayV2 => {
-                use http::header::SET_COOKIE;
                 let mut headers = headers;
                 // ApiGatewayV2 expects the set-cookies headers to be in the "cookies" attribute,
                 // so remove them from the headers.
-                let cookies = headers
-                    .get_all(SET_COOKIE)
-                    .iter()
-                    .cloned()
-                    .map(|v| v.to_str().ok().unwrap_or_default().to_string())
-                    .collect();
-                headers.remove(SET_COOKIE);
+                let cookies = take_cookies(&mut headers);
 
                 LambdaResponse::ApiGatewayV2(ApiGatewayV2httpResponse {
                     body,
@@ -129,6 +130,41 @@ impl LambdaResponse {
                 headers: HeaderMap::new(),
                 multi_value_headers: headers,
             }),
+            #[cfg(feature = "function_url")]
+            RequestOrigin::LambdaFunctionUrl => {
+                use base64::Engine;
+
+                let mut headers = headers;
+                // Function URLs, like ApiGatewayV2, expect the set-cookies headers to be
+                // in the "cookies" attribute.
+                let cookies = take_cookies(&mut headers);
+                let body = body.and_then(|body| match body {
+                    Body::Empty => None,
+                    Body::Text(text) => Some(text),
+                    Body::Binary(data) => Some(base64::engine::general_purpose::STANDARD.encode(data)),
+                });
+
+                LambdaResponse::LambdaFunctionUrl(LambdaFunctionUrlResponse {
+                    body,
+                    is_base64_encoded,
+                    status_code: status_code as i64,
+                    cookies,
+                    headers,
+                })
+            }
+            #[cfg(feature = "vpc_lattice")]
+            RequestOrigin::VpcLattice => LambdaResponse::VpcLattice(VpcLatticeResponse {
+                body,
+                status_code: status_code as i64,
+                is_base64_encoded,
+                // VPC Lattice combines duplicate headers with commas, like API gateway v2.
+                headers,
+                status_description: Some(format!(
+                    "{} {}",
+                    status_code,
+                    parts.status.canonical_reason().unwrap_or_default()
+                )),
+            }),
             #[cfg(feature = "pass_through")]
             RequestOrigin::PassThrough => {
                 match body {
@@ -142,13 +178,29 @@ impl LambdaResponse {
                 feature = "apigw_rest",
                 feature = "apigw_http",
                 feature = "alb",
-                feature = "apigw_websockets"
+                feature = "apigw_websockets",
+                feature = "function_url",
+                feature = "vpc_lattice"
             )))]
-            _ => compile_error!("Either feature `apigw_rest`, `apigw_http`, `alb`, or `apigw_websockets` must be enabled for the `lambda-http` crate."),
+            _ => compile_error!("Either feature `apigw_rest`, `apigw_http`, `alb`, `apigw_websockets`, `function_url`, or `vpc_lattice` must be enabled for the `lambda-http` crate."),
         }
     }
 }
 
+/// Remove the set-cookie headers from the response headers, and return their values.
+#[cfg(any(feature = "apigw_http", feature = "function_url"))]
+fn take_cookies(headers: &mut HeaderMap) -> Vec<String> {
+    use http::header::SET_COOKIE;
+
+    let cookies = headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|v| v.to_str().ok().unwrap_or_default().to_string())
+        .collect();
+    headers.remove(SET_COOKIE);
+    cookies
+}
+
 /// Trait for generating responses
 ///
 /// Types that implement this trait can be used as return types for handler functions.
@@ -482,6 +534,42 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    #[cfg(feature = "function_url")]
+    async fn function_url_response() {
+        let response = Response::builder()
+            .header("set-cookie", "session=abc")
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(HyperBody::from("000000".as_bytes()))
+            .expect("unable to build http::Response");
+        let response = response.into_response().await;
+        let response = LambdaResponse::from_response(&RequestOrigin::LambdaFunctionUrl, response);
+
+        let json = serde_json::to_string(&response).expect("failed to serialize to json");
+        assert_eq!(
+            json,
+            r#"{"statusCode":200,"headers":{"content-type":"application/octet-stream"},"body":"MDAwMDAw","isBase64Encoded":true,"cookies":["session=abc"]}"#
+        )
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "vpc_lattice")]
+    async fn vpc_lattice_response() {
+        let response = Response::builder()
+            .status(StatusCode::CREATED)
+            .header(CONTENT_TYPE, "application/json")
+            .body(HyperBody::from("{}".as_bytes()))
+            .expect("unable to build http::Response");
+        let response = response.into_response().await;
+        let response = LambdaResponse::from_response(&RequestOrigin::VpcLattice, response);
+
+        let json = serde_json::to_string(&response).expect("failed to serialize to json");
+        assert_eq!(
+            json,
+            r#"{"statusCode":201,"statusDescription":"201 Created","headers":{"content-type":"application/json"},"body":"{}","isBase64Encoded":false}"#
+        )
+    }
+
     #[tokio::test]
     async fn content_type_header() {
         // Drive the implementation by using `hyper::Body` instead of
@@ -1,11 +1,13 @@
-use crate::{http::header::SET_COOKIE, request::LambdaRequest, tower::ServiceBuilder, Request, RequestExt};
+use crate::{http::header::SET_COOKIE, request::LambdaRequest, Request, RequestExt};
 use bytes::Bytes;
+use futures_util::{future::MapOk, TryFutureExt};
 pub use http::{self, Response};
 use http_body::Body;
 use lambda_runtime::Diagnostic;
-pub use lambda_runtime::{self, tower::ServiceExt, Error, LambdaEvent, MetadataPrelude, Service, StreamResponse};
+pub use lambda_runtime::{self, Error, LambdaEvent, MetadataPrelude, Service, StreamResponse};
 use std::{
     fmt::Debug,
+    marker::PhantomData,
     pin::Pin,
     task::{Context, Poll},
 };
@@ -16,6 +18,10 @@ use tokio_stream::Stream;
 ///
 /// This takes care of transforming the LambdaEvent into a [`Request`] and
 /// accepts [`http::Response<http_body::Body>`] as response.
+///
+/// Response streaming is supported by Lambda Function URLs. Enable the `function_url`
+/// feature to receive their requests with a [`RequestContext::LambdaFunctionUrl`](crate::request::RequestContext)
+/// context instead of the API Gateway v2 context.
 pub async fn run_with_streaming_response<'a, S, B, E>(handler: S) -> Result<(), Error>
 where
     S: Service<Request, Response = Response<B>, Error = E>,
@@ -25,41 +31,88 @@ where
     B::Data: Into<Bytes> + Send,
     B::Error: Into<Error> + Send + Debug,
 {
-    let svc = ServiceBuilder::new()
-        .map_request(|req: LambdaEvent<LambdaRequest>| {
-            let event: Request = req.payload.into();
-            event.with_lambda_context(req.context)
-        })
-        .service(handler)
-        .map_response(|res| {
-            let (parts, body) = res.into_parts();
-
-            let mut prelude_headers = parts.headers;
-
-            let cookies = prelude_headers.get_all(SET_COOKIE);
-            let cookies = cookies
-                .iter()
-                .map(|c| String::from_utf8_lossy(c.as_bytes()).to_string())
-                .collect::<Vec<String>>();
-
-            prelude_headers.remove(SET_COOKIE);
-
-            let metadata_prelude = MetadataPrelude {
-                headers: prelude_headers,
-                status_code: parts.status,
-                cookies,
-            };
-
-            StreamResponse {
-                metadata_prelude,
-                stream: BodyStream { body },
-            }
-        });
+    lambda_runtime::run(StreamAdapter::from(handler)).await
+}
+
+/// Wraps a `Service<Request>` that returns streaming responses in a
+/// `Service<LambdaEvent<LambdaRequest>>`.
+///
+/// This is what [run_with_streaming_response] runs. Use it directly to add middleware
+/// between the runtime and the conversion of the `LambdaEvent` into a [`Request`], or to
+/// run the handler with a [`lambda_runtime::Runtime`] that you configure yourself.
+pub struct StreamAdapter<'a, S, B> {
+    service: S,
+    _phantom_data: PhantomData<&'a B>,
+}
 
-    lambda_runtime::run(svc).await
+impl<'a, S, B, E> From<S> for StreamAdapter<'a, S, B>
+where
+    S: Service<Request, Response = Response<B>, Error = E>,
+    S::Future: Send + 'a,
+    B: Body + Unpin + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<Error> + Send + Debug,
+{
+    fn from(service: S) -> Self {
+        StreamAdapter {
+            service,
+            _phantom_data: PhantomData,
+        }
+    }
+}
+
+impl<'a, S, B, E> Service<LambdaEvent<LambdaRequest>> for StreamAdapter<'a, S, B>
+where
+    S: Service<Request, Response = Response<B>, Error = E>,
+    S::Future: Send + 'a,
+    B: Body + Unpin + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<Error> + Send + Debug,
+{
+    type Response = StreamResponse<BodyStream<B>>;
+    type Error = E;
+    type Future = MapOk<S::Future, fn(Response<B>) -> StreamResponse<BodyStream<B>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaEvent<LambdaRequest>) -> Self::Future {
+        let event: Request = req.payload.into();
+        self.service
+            .call(event.with_lambda_context(req.context))
+            .map_ok(into_stream_response)
+    }
+}
+
+/// Split the response into the metadata prelude sent before the body, and the streamed body.
+fn into_stream_response<B>(res: Response<B>) -> StreamResponse<BodyStream<B>> {
+    let (parts, body) = res.into_parts();
+
+    let mut prelude_headers = parts.headers;
+
+    let cookies = prelude_headers.get_all(SET_COOKIE);
+    let cookies = cookies
+        .iter()
+        .map(|c| String::from_utf8_lossy(c.as_bytes()).to_string())
+        .collect::<Vec<String>>();
+
+    prelude_headers.remove(SET_COOKIE);
+
+    let metadata_prelude = MetadataPrelude {
+        headers: prelude_headers,
+        status_code: parts.status,
+        cookies,
+    };
+
+    StreamResponse {
+        metadata_prelude,
+        stream: BodyStream { body },
+    }
 }
 
 pin_project_lite::pin_project! {
+/// Stream of the data frames of a response body, sent after the metadata prelude.
 pub struct BodyStream<B> {
     #[pin]
     pub(crate) body: B,
@@ -85,3 +138,50 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::StatusCode;
+    use http_body_util::Full;
+
+    #[test]
+    fn cookies_are_sent_in_the_metadata_prelude() {
+        let response = Response::builder()
+            .status(StatusCode::CREATED)
+            .header("content-type", "text/plain")
+            .header(SET_COOKIE, "session=1")
+            .header(SET_COOKIE, "theme=dark")
+            .body(Full::new(Bytes::from_static(b"hello")))
+            .unwrap();
+
+        let stream = into_stream_response(response);
+        let prelude = stream.metadata_prelude;
+        assert_eq!(StatusCode::CREATED, prelude.status_code);
+        assert_eq!(vec!["session=1", "theme=dark"], prelude.cookies);
+        assert_eq!("text/plain", prelude.headers["content-type"]);
+        assert!(prelude.headers.get(SET_COOKIE).is_none());
+    }
+
+    #[cfg(feature = "function_url")]
+    #[tokio::test]
+    async fn function_url_requests_are_streamed() {
+        use crate::{request::RequestContext, service_fn, tower::ServiceExt};
+        use std::convert::Infallible;
+        use tokio_stream::StreamExt;
+
+        let input = include_str!("../tests/data/lambda_function_url_request.json");
+        let payload: LambdaRequest = serde_json::from_str(input).unwrap();
+        let event = LambdaEvent::new(payload, Default::default());
+
+        let handler = service_fn(|req: Request| async move {
+            assert!(matches!(req.request_context(), RequestContext::LambdaFunctionUrl(_)));
+            Ok::<_, Infallible>(Response::new(Full::new(Bytes::from_static(b"streamed"))))
+        });
+        let response = StreamAdapter::from(handler).oneshot(event).await.unwrap();
+        assert_eq!(StatusCode::OK, response.metadata_prelude.status_code);
+
+        let body: Vec<Bytes> = response.stream.collect::<Result<_, _>>().await.unwrap();
+        assert_eq!(vec![Bytes::from_static(b"streamed")], body);
+    }
+}
@@ -0,0 +1,168 @@
+//! Model of the [Lambda Extensions API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-extensions-api.html).
+use super::{empty_request, json_request, required_header};
+use crate::{body::Body, Error};
+use http::{HeaderMap, Method, Request};
+use serde::{Deserialize, Serialize};
+
+/// Header with the name of the extension, sent when the extension is registered.
+pub const EXTENSION_NAME_HEADER: &str = "Lambda-Extension-Name";
+/// Header with the unique id of the extension, returned when the extension is registered
+/// and sent in every other request.
+pub const EXTENSION_ID_HEADER: &str = "Lambda-Extension-Identifier";
+/// Header with the type of the error reported by the extension.
+pub const EXTENSION_ERROR_TYPE_HEADER: &str = "Lambda-Extension-Function-Error-Type";
+/// Header with the comma separated list of optional features the extension supports.
+pub const EXTENSION_ACCEPT_FEATURE_HEADER: &str = "Lambda-Extension-Accept-Feature";
+
+/// Feature that adds the id of the account to the register response.
+pub const ACCOUNT_ID_FEATURE: &str = "accountId";
+
+const REGISTER_PATH: &str = "/2020-01-01/extension/register";
+const NEXT_EVENT_PATH: &str = "/2020-01-01/extension/event/next";
+
+/// Payload of a `/extension/register` request.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RegisterRequest<'a> {
+    /// The lifecycle events the extension subscribes to, `INVOKE` and `SHUTDOWN`.
+    #[serde(borrow)]
+    pub events: Vec<&'a str>,
+}
+
+/// Build a `POST /extension/register` request to register an extension for the given events.
+///
+/// The request asks for the [ACCOUNT_ID_FEATURE], so the response includes the id of the account.
+pub fn register(extension_name: &str, events: &[&str]) -> Result<Request<Body>, Error> {
+    let body = RegisterRequest {
+        events: events.to_vec(),
+    };
+    json_request(
+        Method::POST,
+        REGISTER_PATH,
+        &[
+            (EXTENSION_NAME_HEADER, extension_name),
+            (EXTENSION_ACCEPT_FEATURE_HEADER, ACCOUNT_ID_FEATURE),
+        ],
+        &body,
+    )
+}
+
+/// Build a `GET /extension/event/next` request to wait for the next lifecycle event.
+pub fn next_event(extension_id: &str) -> Result<Request<Body>, Error> {
+    empty_request(Method::GET, NEXT_EVENT_PATH, &[(EXTENSION_ID_HEADER, extension_id)])
+}
+
+/// Build a `POST /extension/init/error` request to report an error that happened
+/// while the extension was initializing.
+pub fn init_error(
+    extension_id: &str,
+    error_type: &str,
+    request: Option<&ErrorRequest<'_>>,
+) -> Result<Request<Body>, Error> {
+    error_request("init", extension_id, error_type, request)
+}
+
+/// Build a `POST /extension/exit/error` request to report an error that makes
+/// the extension exit.
+pub fn exit_error(
+    extension_id: &str,
+    error_type: &str,
+    request: Option<&ErrorRequest<'_>>,
+) -> Result<Request<Body>, Error> {
+    error_request("exit", extension_id, error_type, request)
+}
+
+fn error_request(
+    phase: &str,
+    extension_id: &str,
+    error_type: &str,
+    request: Option<&ErrorRequest<'_>>,
+) -> Result<Request<Body>, Error> {
+    let path = format!("/2020-01-01/extension/{phase}/error");
+    let headers = [
+        (EXTENSION_ID_HEADER, extension_id),
+        (EXTENSION_ERROR_TYPE_HEADER, error_type),
+    ];
+    match request {
+        None => empty_request(Method::POST, &path, &headers),
+        Some(request) => json_request(Method::POST, &path, &headers, request),
+    }
+}
+
+/// Payload to send error information to the Extensions API.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorRequest<'a> {
+    /// Human readable error description
+    pub error_message: &'a str,
+    /// The type of error to categorize
+    pub error_type: &'a str,
+    /// The error backtrace
+    pub stack_trace: Vec<&'a str>,
+}
+
+/// Response of the `/extension/register` endpoint.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterResponse {
+    /// The unique id of the extension, returned in the [EXTENSION_ID_HEADER] header.
+    #[serde(skip)]
+    pub extension_id: String,
+    /// The name of the function the extension is registered with.
+    pub function_name: String,
+    /// The version of the function the extension is registered with.
+    pub function_version: String,
+    /// The handler of the function the extension is registered with.
+    pub handler: String,
+    /// The id of the account the function belongs to.
+    /// This is `None` unless the [ACCOUNT_ID_FEATURE] was requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+}
+
+impl RegisterResponse {
+    /// Parse the headers and the body of a `/extension/register` response.
+    pub fn from_parts(headers: &HeaderMap, body: &[u8]) -> Result<Self, Error> {
+        let extension_id = required_header(headers, EXTENSION_ID_HEADER)?.to_owned();
+        let response: RegisterResponse = serde_json::from_slice(body).map_err(Error::new)?;
+        Ok(RegisterResponse {
+            extension_id,
+            ..response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_response_from_parts() {
+        let mut headers = HeaderMap::new();
+        headers.insert(EXTENSION_ID_HEADER, "ext-1".parse().unwrap());
+        let body =
+            br#"{"functionName":"hello","functionVersion":"$LATEST","handler":"bootstrap","accountId":"123456789012"}"#;
+
+        let response = RegisterResponse::from_parts(&headers, body).unwrap();
+        assert_eq!("ext-1", response.extension_id);
+        assert_eq!("hello", response.function_name);
+        assert_eq!("$LATEST", response.function_version);
+        assert_eq!(Some("123456789012"), response.account_id.as_deref());
+    }
+
+    #[test]
+    fn register_request_asks_for_the_account_id() {
+        let req = register("my-extension", &["INVOKE", "SHUTDOWN"]).unwrap();
+        assert_eq!("/2020-01-01/extension/register", req.uri().path());
+        assert_eq!("my-extension", req.headers()[EXTENSION_NAME_HEADER]);
+        assert_eq!(ACCOUNT_ID_FEATURE, req.headers()[EXTENSION_ACCEPT_FEATURE_HEADER]);
+    }
+
+    #[test]
+    fn errors_without_payload_have_an_empty_body() {
+        let req = exit_error("ext-1", "Extension.Crash", None).unwrap();
+        assert_eq!("/2020-01-01/extension/exit/error", req.uri().path());
+        assert_eq!("ext-1", req.headers()[EXTENSION_ID_HEADER]);
+        assert_eq!("Extension.Crash", req.headers()[EXTENSION_ERROR_TYPE_HEADER]);
+        assert!(req.headers().get(http::header::CONTENT_TYPE).is_none());
+    }
+}
@@ -0,0 +1,66 @@
+//! Typed model of the Lambda Runtime, Extensions, Logs and Telemetry APIs.
+//!
+//! Each submodule exposes the header names used by an API, functions that build
+//! its requests ready to be sent with [Client::call](crate::Client::call), and the
+//! responses of the API parsed into structs.
+use crate::{body::Body, build_request, Error};
+use http::{header::CONTENT_TYPE, HeaderMap, Method, Request};
+use serde::Serialize;
+
+pub mod extension;
+pub mod runtime;
+pub mod subscription;
+
+const CONTENT_TYPE_JSON: &str = "application/json";
+
+/// Build a request with a JSON body.
+fn json_request<T: Serialize + ?Sized>(
+    method: Method,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: &T,
+) -> Result<Request<Body>, Error> {
+    let body = serde_json::to_vec(body).map_err(Error::new)?;
+    let mut builder = build_request()
+        .method(method)
+        .uri(uri)
+        .header(CONTENT_TYPE, CONTENT_TYPE_JSON);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    builder.body(Body::from(body)).map_err(Error::new)
+}
+
+/// Build a request without a body.
+fn empty_request(method: Method, uri: &str, headers: &[(&str, &str)]) -> Result<Request<Body>, Error> {
+    let mut builder = build_request().method(method).uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    builder.body(Body::empty()).map_err(Error::new)
+}
+
+/// Get the value of a header that must be present in a response.
+fn required_header<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, Error> {
+    optional_header(headers, name)?.ok_or_else(|| Error::new(format!("missing {name} header")))
+}
+
+/// Get the value of a header that may be missing from a response.
+fn optional_header<'a>(headers: &'a HeaderMap, name: &str) -> Result<Option<&'a str>, Error> {
+    headers
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_| Error::new(format!("invalid {name} header: {value:?}")))
+        })
+        .transpose()
+}
+
+/// Get the value of a header that may be missing from a response, replacing
+/// invalid UTF-8 sequences instead of failing.
+fn lossy_header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+}
@@ -0,0 +1,223 @@
+//! Model of the [Lambda Runtime API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
+use super::{empty_request, json_request, lossy_header, optional_header, required_header};
+use crate::{body::Body, build_request, Error};
+use http::{HeaderMap, Method, Request};
+use serde::Serialize;
+
+/// Header with the id of the request that invoked the function.
+pub const REQUEST_ID_HEADER: &str = "lambda-runtime-aws-request-id";
+/// Header with the date the function times out, in Unix time milliseconds.
+pub const DEADLINE_HEADER: &str = "lambda-runtime-deadline-ms";
+/// Header with the ARN of the function, version, or alias specified in the invocation.
+pub const INVOKED_FUNCTION_ARN_HEADER: &str = "lambda-runtime-invoked-function-arn";
+/// Header with the AWS X-Ray tracing header of the invocation.
+pub const TRACE_ID_HEADER: &str = "lambda-runtime-trace-id";
+/// Header with the client context sent by the AWS Mobile SDK.
+pub const CLIENT_CONTEXT_HEADER: &str = "lambda-runtime-client-context";
+/// Header with the Amazon Cognito identity sent by the AWS Mobile SDK.
+pub const COGNITO_IDENTITY_HEADER: &str = "lambda-runtime-cognito-identity";
+/// Header with the type of the error reported by the function.
+pub const FUNCTION_ERROR_TYPE_HEADER: &str = "Lambda-Runtime-Function-Error-Type";
+/// Trailer with the base64 encoded body of an error that happened while streaming a response.
+pub const FUNCTION_ERROR_BODY_HEADER: &str = "Lambda-Runtime-Function-Error-Body";
+/// Header with the cause of an error, in the X-Ray error cause format.
+pub const XRAY_ERROR_CAUSE_HEADER: &str = "Lambda-Runtime-Function-XRay-Error-Cause";
+/// Header that sets the mode of a response, `streaming` for streaming responses.
+pub const RESPONSE_MODE_HEADER: &str = "Lambda-Runtime-Function-Response-Mode";
+
+const NEXT_INVOCATION_PATH: &str = "/2018-06-01/runtime/invocation/next";
+const INIT_ERROR_PATH: &str = "/2018-06-01/runtime/init/error";
+const RESTORE_NEXT_PATH: &str = "/2018-06-01/runtime/restore/next";
+const RESTORE_ERROR_PATH: &str = "/2018-06-01/runtime/restore/error";
+
+/// Build a `GET /runtime/invocation/next` request to wait for the next invocation.
+pub fn next_invocation() -> Result<Request<Body>, Error> {
+    empty_request(Method::GET, NEXT_INVOCATION_PATH, &[])
+}
+
+/// Build a `POST /runtime/invocation/{request_id}/response` request to send
+/// the response of an invocation.
+pub fn invocation_response(request_id: &str, body: impl Into<Body>) -> Result<Request<Body>, Error> {
+    build_request()
+        .method(Method::POST)
+        .uri(invocation_response_path(request_id))
+        .body(body.into())
+        .map_err(Error::new)
+}
+
+/// Build a `POST /runtime/invocation/{request_id}/error` request to report
+/// the error of an invocation.
+pub fn invocation_error<T: Serialize + ?Sized>(
+    request_id: &str,
+    error_type: &str,
+    error: &T,
+) -> Result<Request<Body>, Error> {
+    let path = format!("/2018-06-01/runtime/invocation/{request_id}/error");
+    json_request(Method::POST, &path, &[(FUNCTION_ERROR_TYPE_HEADER, error_type)], error)
+}
+
+/// Build a `POST /runtime/init/error` request to report an error that happened
+/// while the function was initializing.
+pub fn init_error<T: Serialize + ?Sized>(error_type: &str, error: &T) -> Result<Request<Body>, Error> {
+    json_request(
+        Method::POST,
+        INIT_ERROR_PATH,
+        &[(FUNCTION_ERROR_TYPE_HEADER, error_type)],
+        error,
+    )
+}
+
+/// Build a `GET /runtime/restore/next` request to wait for a SnapStart snapshot to be restored.
+pub fn restore_next() -> Result<Request<Body>, Error> {
+    empty_request(Method::GET, RESTORE_NEXT_PATH, &[])
+}
+
+/// Build a `POST /runtime/restore/error` request to report an error that happened
+/// while a SnapStart snapshot was restored.
+pub fn restore_error<T: Serialize + ?Sized>(error_type: &str, error: &T) -> Result<Request<Body>, Error> {
+    json_request(
+        Method::POST,
+        RESTORE_ERROR_PATH,
+        &[(FUNCTION_ERROR_TYPE_HEADER, error_type)],
+        error,
+    )
+}
+
+/// Path of the `/runtime/invocation/{request_id}/response` endpoint.
+pub fn invocation_response_path(request_id: &str) -> String {
+    format!("/2018-06-01/runtime/invocation/{request_id}/response")
+}
+
+/// Invocation returned by the `/runtime/invocation/next` endpoint, parsed from the
+/// headers of the response. The body of the response is the payload of the invocation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NextInvocation {
+    /// The id of the request that invoked the function.
+    pub request_id: String,
+    /// The date the function times out, in Unix time milliseconds.
+    pub deadline_ms: u64,
+    /// The ARN of the function, version, or alias specified in the invocation, if the
+    /// Runtime API sent it.
+    pub invoked_function_arn: Option<String>,
+    /// The AWS X-Ray tracing header of the invocation.
+    pub trace_id: Option<String>,
+    /// The client context sent by the AWS Mobile SDK, as JSON.
+    pub client_context: Option<String>,
+    /// The Amazon Cognito identity sent by the AWS Mobile SDK, as JSON.
+    pub cognito_identity: Option<String>,
+}
+
+impl NextInvocation {
+    /// Parse the headers of a `/runtime/invocation/next` response.
+    ///
+    /// Only a missing or invalid request id or deadline is an error. The function ARN
+    /// and the X-Ray trace id are optional, and read lossily when they are not valid UTF-8.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, Error> {
+        let deadline = required_header(headers, DEADLINE_HEADER)?;
+        let deadline_ms = deadline
+            .parse()
+            .map_err(|_| Error::new(format!("invalid {DEADLINE_HEADER} header: {deadline}")))?;
+        Ok(NextInvocation {
+            request_id: required_header(headers, REQUEST_ID_HEADER)?.to_owned(),
+            deadline_ms,
+            invoked_function_arn: lossy_header(headers, INVOKED_FUNCTION_ARN_HEADER),
+            trace_id: lossy_header(headers, TRACE_ID_HEADER),
+            client_context: optional_header(headers, CLIENT_CONTEXT_HEADER)?.map(str::to_owned),
+            cognito_identity: optional_header(headers, COGNITO_IDENTITY_HEADER)?.map(str::to_owned),
+        })
+    }
+
+    /// Headers of a `/runtime/invocation/next` response for this invocation.
+    ///
+    /// This is the inverse of [NextInvocation::from_headers], useful to build a fake
+    /// Runtime API in tests.
+    pub fn to_headers(&self) -> Result<HeaderMap, Error> {
+        let mut headers = HeaderMap::new();
+        let mut insert = |name: &'static str, value: &str| -> Result<(), Error> {
+            headers.insert(name, value.parse().map_err(Error::new)?);
+            Ok(())
+        };
+        insert(REQUEST_ID_HEADER, &self.request_id)?;
+        insert(DEADLINE_HEADER, &self.deadline_ms.to_string())?;
+        if let Some(invoked_function_arn) = &self.invoked_function_arn {
+            insert(INVOKED_FUNCTION_ARN_HEADER, invoked_function_arn)?;
+        }
+        if let Some(trace_id) = &self.trace_id {
+            insert(TRACE_ID_HEADER, trace_id)?;
+        }
+        if let Some(client_context) = &self.client_context {
+            insert(CLIENT_CONTEXT_HEADER, client_context)?;
+        }
+        if let Some(cognito_identity) = &self.cognito_identity {
+            insert(COGNITO_IDENTITY_HEADER, cognito_identity)?;
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_invocation_round_trip() {
+        let invocation = NextInvocation {
+            request_id: "8476a536-e9f4-11e8-9739-2dfe598c3fcd".into(),
+            deadline_ms: 1542409706888,
+            invoked_function_arn: Some("arn:aws:lambda:us-east-2:123456789012:function:custom-runtime".into()),
+            trace_id: Some("Root=1-5bef4de7-ad49b0e87f6ef6c87fc2e700".into()),
+            client_context: None,
+            cognito_identity: None,
+        };
+        let headers = invocation.to_headers().unwrap();
+        assert_eq!(invocation, NextInvocation::from_headers(&headers).unwrap());
+    }
+
+    #[test]
+    fn next_invocation_requires_a_request_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert(DEADLINE_HEADER, "1542409706888".parse().unwrap());
+        let err = NextInvocation::from_headers(&headers).unwrap_err();
+        assert_eq!("missing lambda-runtime-aws-request-id header", err.to_string());
+    }
+
+    #[test]
+    fn next_invocation_requires_a_valid_deadline() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            REQUEST_ID_HEADER,
+            "8476a536-e9f4-11e8-9739-2dfe598c3fcd".parse().unwrap(),
+        );
+        headers.insert(DEADLINE_HEADER, "soon".parse().unwrap());
+        let err = NextInvocation::from_headers(&headers).unwrap_err();
+        assert_eq!("invalid lambda-runtime-deadline-ms header: soon", err.to_string());
+    }
+
+    #[test]
+    fn next_invocation_trace_id_and_arn_are_optional() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            REQUEST_ID_HEADER,
+            "8476a536-e9f4-11e8-9739-2dfe598c3fcd".parse().unwrap(),
+        );
+        headers.insert(DEADLINE_HEADER, "1542409706888".parse().unwrap());
+
+        let invocation = NextInvocation::from_headers(&headers).unwrap();
+        assert_eq!(None, invocation.invoked_function_arn);
+        assert_eq!(None, invocation.trace_id);
+
+        headers.insert(TRACE_ID_HEADER, http::HeaderValue::from_bytes(b"Root=1-\xff").unwrap());
+        let invocation = NextInvocation::from_headers(&headers).unwrap();
+        assert_eq!(Some("Root=1-\u{fffd}"), invocation.trace_id.as_deref());
+    }
+
+    #[test]
+    fn invocation_error_sets_the_error_type() {
+        let error = serde_json::json!({ "errorType": "InvalidEvent", "errorMessage": "bad payload" });
+        let req = invocation_error("id", "unhandled", &error).unwrap();
+        assert_eq!(Method::POST, req.method());
+        assert_eq!("/2018-06-01/runtime/invocation/id/error", req.uri().path());
+        assert_eq!("unhandled", req.headers()[FUNCTION_ERROR_TYPE_HEADER]);
+        assert_eq!("application/json", req.headers()[http::header::CONTENT_TYPE]);
+    }
+}
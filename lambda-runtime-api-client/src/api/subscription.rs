@@ -0,0 +1,117 @@
+//! Model of the subscription requests of the [Lambda Logs API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-logs-api.html)
+//! and the [Lambda Telemetry API](https://docs.aws.amazon.com/lambda/latest/dg/telemetry-api.html).
+use super::{extension::EXTENSION_ID_HEADER, json_request};
+use crate::{body::Body, Error};
+use http::{Method, Request};
+use serde::Serialize;
+
+/// API an extension subscribes to, to receive the logs or the telemetry of the function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionApi {
+    /// The Logs API.
+    Logs,
+    /// The Telemetry API.
+    Telemetry,
+}
+
+impl SubscriptionApi {
+    /// The version of the schema of the events sent by the API.
+    pub fn schema_version(&self) -> &'static str {
+        match self {
+            SubscriptionApi::Logs => "2021-03-18",
+            SubscriptionApi::Telemetry => "2022-07-01",
+        }
+    }
+
+    /// The path of the subscription endpoint of the API.
+    pub fn path(&self) -> &'static str {
+        match self {
+            SubscriptionApi::Logs => "/2020-08-15/logs",
+            SubscriptionApi::Telemetry => "/2022-07-01/telemetry",
+        }
+    }
+}
+
+/// Payload of a subscription request, generic over the buffering configuration.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscribeRequest<'a, B> {
+    /// The version of the schema of the events to receive.
+    pub schema_version: &'static str,
+    /// The types of events to receive, like `platform`, `function` and `extension`.
+    pub types: &'a [&'a str],
+    /// How the events are buffered before they are sent to the destination.
+    pub buffering: B,
+    /// Where the events are sent.
+    pub destination: Destination,
+}
+
+impl<'a, B> SubscribeRequest<'a, B> {
+    /// Create a new subscription to `api` that sends the events to an HTTP server
+    /// listening on `port` in the execution environment.
+    pub fn new(api: SubscriptionApi, types: &'a [&'a str], buffering: B, port: u16) -> Self {
+        SubscribeRequest {
+            schema_version: api.schema_version(),
+            types,
+            buffering,
+            destination: Destination::http(port),
+        }
+    }
+}
+
+/// Destination of the events of a subscription.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Destination {
+    /// The protocol used to send the events, always `HTTP`.
+    pub protocol: &'static str,
+    /// The URI the events are sent to.
+    #[serde(rename = "URI")]
+    pub uri: String,
+}
+
+impl Destination {
+    /// Destination for an HTTP server listening on `port` in the execution environment.
+    pub fn http(port: u16) -> Self {
+        Destination {
+            protocol: "HTTP",
+            uri: format!("http://sandbox.localdomain:{port}"),
+        }
+    }
+}
+
+/// Build a `PUT` request to subscribe an extension to the Logs or the Telemetry API.
+pub fn subscribe<B: Serialize>(
+    api: SubscriptionApi,
+    extension_id: &str,
+    request: &SubscribeRequest<'_, B>,
+) -> Result<Request<Body>, Error> {
+    json_request(Method::PUT, api.path(), &[(EXTENSION_ID_HEADER, extension_id)], request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscribe_to_telemetry() {
+        let buffering = serde_json::json!({ "timeoutMs": 100 });
+        let request = SubscribeRequest::new(SubscriptionApi::Telemetry, &["platform"], buffering, 9002);
+        let req = subscribe(SubscriptionApi::Telemetry, "ext-1", &request).unwrap();
+
+        assert_eq!(Method::PUT, req.method());
+        assert_eq!("/2022-07-01/telemetry", req.uri().path());
+        assert_eq!("ext-1", req.headers()[EXTENSION_ID_HEADER]);
+
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "schemaVersion": "2022-07-01",
+                "types": ["platform"],
+                "buffering": { "timeoutMs": 100 },
+                "destination": { "protocol": "HTTP", "URI": "http://sandbox.localdomain:9002" }
+            }),
+            body
+        );
+    }
+}
@@ -9,9 +9,16 @@ use http::{
     uri::{PathAndQuery, Scheme},
     Request, Response, Uri,
 };
+use http_body::Body as _;
 use hyper::body::Incoming;
-use hyper_util::client::legacy::connect::HttpConnector;
-use std::{convert::TryInto, fmt::Debug, future};
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use std::{
+    fmt::Debug,
+    future,
+    time::{Duration, Instant},
+};
+use tower::Service;
+use transport::Transport;
 
 const USER_AGENT_HEADER: &str = "User-Agent";
 const DEFAULT_USER_AGENT: &str = concat!("aws-lambda-rust/", env!("CARGO_PKG_VERSION"));
@@ -19,26 +26,33 @@ const CUSTOM_USER_AGENT: Option<&str> = option_env!("LAMBDA_RUNTIME_USER_AGENT")
 
 mod error;
 pub use error::*;
+pub mod api;
 pub mod body;
+mod retry;
+pub use retry::RetryPolicy;
+mod transport;
 
 #[cfg(feature = "tracing")]
 pub mod tracing;
 
 /// API client to interact with the AWS Lambda Runtime API.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Client {
     /// The runtime API URI
     pub base: Uri,
-    /// The client that manages the API connections
-    pub client: hyper_util::client::legacy::Client<HttpConnector, body::Body>,
+    /// The transport that sends the requests to the API
+    transport: Transport,
+    /// The policy to retry requests that fail with a transient error, if any
+    pub retry_policy: Option<RetryPolicy>,
 }
 
 impl Client {
     /// Create a builder struct to configure the client.
     pub fn builder() -> ClientBuilder {
         ClientBuilder {
-            connector: HttpConnector::new(),
+            transport: None,
             uri: None,
+            retry_policy: None,
         }
     }
 }
@@ -54,15 +68,25 @@ impl Client {
             Ok(req) => req,
             Err(err) => return future::ready(Err(err)).boxed(),
         };
-        self.client.request(req).map_err(Into::into).boxed()
-    }
+        #[cfg(feature = "tracing")]
+        let (method, path) = (req.method().clone(), req.uri().path().to_string());
 
-    /// Create a new client with a given base URI and HTTP connector.
-    fn with(base: Uri, connector: HttpConnector) -> Self {
-        let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
-            .http1_max_buf_size(1024 * 1024)
-            .build(connector);
-        Self { base, client }
+        let start = Instant::now();
+        let res = match &self.retry_policy {
+            // Only buffered bodies have an exact size, streaming bodies cannot be sent again.
+            Some(policy) if req.body().size_hint().exact().is_some() => {
+                retry::send_with_retries(self.transport.clone(), policy.clone(), req).boxed()
+            }
+            _ => self.transport.send(req),
+        };
+        res.map_ok(move |mut res| {
+            let latency = start.elapsed();
+            #[cfg(feature = "tracing")]
+            tracing::trace!(%method, path, status = %res.status(), ?latency, "Runtime API call completed");
+            res.extensions_mut().insert(CallLatency(latency));
+            res
+        })
+        .boxed()
     }
 
     fn set_origin<B>(&self, req: Request<B>) -> Result<Request<B>, BoxError> {
@@ -88,18 +112,54 @@ impl Client {
     }
 }
 
+/// Time elapsed between sending a request to the Runtime API and receiving the headers
+/// of its response, including retries.
+///
+/// [Client::call] adds this value to the extensions of every response. For requests with
+/// a streaming body, it includes the time spent uploading the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallLatency(pub Duration);
+
 /// Builder implementation to construct any Runtime API clients.
 pub struct ClientBuilder {
-    connector: HttpConnector,
+    transport: Option<Transport>,
     uri: Option<http::Uri>,
+    retry_policy: Option<RetryPolicy>,
 }
 
 impl ClientBuilder {
-    /// Create a new builder with a given HTTP connector.
-    pub fn with_connector(self, connector: HttpConnector) -> ClientBuilder {
+    /// Create a new builder with a given connector.
+    ///
+    /// The client sends requests with a `hyper` HTTP/1 client over connections opened by the
+    /// connector. Use this method to connect to the Runtime API over a different transport,
+    /// like a Unix socket, an in-memory stream in tests, or TLS for an emulator of the API.
+    /// By default, the client uses an [HttpConnector].
+    pub fn with_connector<C>(self, connector: C) -> ClientBuilder
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
         ClientBuilder {
-            connector,
-            uri: self.uri,
+            transport: Some(Transport::from_connector(connector)),
+            ..self
+        }
+    }
+
+    /// Create a new builder that sends requests with a given tower service.
+    ///
+    /// The service receives requests with an absolute URI, built from the endpoint of the
+    /// client. Use this method to wrap the HTTP client in middleware, like request logging.
+    /// A `hyper_util` legacy client is the usual innermost service.
+    ///
+    /// This replaces the connector set with [ClientBuilder::with_connector].
+    pub fn with_transport<S>(self, transport: S) -> ClientBuilder
+    where
+        S: Service<Request<body::Body>, Response = Response<Incoming>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        ClientBuilder {
+            transport: Some(Transport::from_service(transport)),
+            ..self
         }
     }
 
@@ -109,16 +169,38 @@ impl ClientBuilder {
         Self { uri: Some(uri), ..self }
     }
 
+    /// Retry requests that fail with a transient error according to the given policy.
+    /// By default, failed requests are not retried.
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
     /// Create the new client to interact with the Runtime API.
+    ///
+    /// If no endpoint has been set with [ClientBuilder::with_endpoint], the endpoint is read from the
+    /// `AWS_LAMBDA_RUNTIME_API` environment variable. This function returns an error if that
+    /// variable is missing or if it's not a valid URI.
     pub fn build(self) -> Result<Client, Error> {
         let uri = match self.uri {
             Some(uri) => uri,
             None => {
-                let uri = std::env::var("AWS_LAMBDA_RUNTIME_API").expect("Missing AWS_LAMBDA_RUNTIME_API env var");
-                uri.try_into().expect("Unable to convert to URL")
+                let uri = std::env::var("AWS_LAMBDA_RUNTIME_API")
+                    .map_err(|_| Error::new("missing AWS_LAMBDA_RUNTIME_API env var"))?;
+                http::Uri::try_from(uri).map_err(Error::new)?
             }
         };
-        Ok(Client::with(uri, self.connector))
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => Transport::from_connector(HttpConnector::new()),
+        };
+        Ok(Client {
+            base: uri,
+            transport,
+            retry_policy: self.retry_policy,
+        })
     }
 }
 
@@ -138,6 +220,125 @@ pub fn build_request() -> http::request::Builder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+    use hyper::{
+        rt::{Read, ReadBufCursor, Write},
+        server::conn::http1,
+        service::service_fn,
+    };
+    use hyper_util::{
+        client::legacy::connect::{Connected, Connection},
+        rt::{TokioExecutor, TokioIo},
+    };
+    use std::{
+        convert::Infallible,
+        io,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+    };
+    use tokio::io::DuplexStream;
+
+    /// Connector that serves every connection with an in-memory HTTP server,
+    /// which responds with the path of the request.
+    #[derive(Clone)]
+    struct InMemoryConnector;
+
+    struct InMemoryStream(TokioIo<DuplexStream>);
+
+    impl Service<Uri> for InMemoryConnector {
+        type Response = InMemoryStream;
+        type Error = BoxError;
+        type Future = future::Ready<Result<InMemoryStream, BoxError>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _uri: Uri) -> Self::Future {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            let service = service_fn(|req: Request<Incoming>| async move {
+                let path = Bytes::from(req.uri().path().to_string());
+                Ok::<_, Infallible>(Response::new(Full::new(path)))
+            });
+            tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(server), service));
+            future::ready(Ok(InMemoryStream(TokioIo::new(client))))
+        }
+    }
+
+    impl Connection for InMemoryStream {
+        fn connected(&self) -> Connected {
+            Connected::new()
+        }
+    }
+
+    impl Read for InMemoryStream {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: ReadBufCursor<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_read(cx, buf)
+        }
+    }
+
+    impl Write for InMemoryStream {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_shutdown(cx)
+        }
+    }
+
+    fn next_event_request() -> Request<body::Body> {
+        build_request()
+            .uri("/2018-06-01/runtime/invocation/next")
+            .body(body::Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_with_connector() {
+        let client = Client::builder()
+            .with_endpoint("http://localhost:9001".parse().unwrap())
+            .with_connector(InMemoryConnector)
+            .build()
+            .unwrap();
+
+        let res = client.call(next_event_request()).await.unwrap();
+        assert!(res.extensions().get::<CallLatency>().is_some());
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!("/2018-06-01/runtime/invocation/next", body);
+    }
+
+    #[tokio::test]
+    async fn test_client_with_transport() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let http = hyper_util::client::legacy::Client::builder(TokioExecutor::new()).build(InMemoryConnector);
+        let transport = tower::ServiceBuilder::new()
+            .map_request(move |req: Request<body::Body>| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                req
+            })
+            .service(http);
+
+        let client = Client::builder()
+            .with_endpoint("http://localhost:9001".parse().unwrap())
+            .with_transport(transport)
+            .build()
+            .unwrap();
+
+        let res = client.call(next_event_request()).await.unwrap();
+        assert!(res.status().is_success());
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+    }
 
     #[test]
     fn test_set_origin() {
@@ -0,0 +1,212 @@
+use crate::{body::Body, transport::Transport, BoxError};
+use bytes::Bytes;
+use http::{request::Parts, Request, Response};
+use hyper::body::Incoming;
+use std::time::Duration;
+
+/// Policy to retry the requests to the Runtime API that fail with a transient error.
+///
+/// The client retries requests that fail before a response is received, like connection
+/// resets, and requests that receive a `5xx` response. Retries are delayed with a bounded
+/// exponential backoff. Requests with a streaming body are never retried, because their
+/// body cannot be sent again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Create a policy that retries a failed request up to `max_retries` times.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    /// Set the delay before the first retry. The delay doubles with every retry.
+    pub fn with_initial_backoff(self, initial_backoff: Duration) -> Self {
+        Self {
+            initial_backoff,
+            ..self
+        }
+    }
+
+    /// Set the maximum delay between two retries.
+    pub fn with_max_backoff(self, max_backoff: Duration) -> Self {
+        Self { max_backoff, ..self }
+    }
+
+    /// The delay before the given retry, starting at zero.
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Retry up to 3 times, waiting 50 milliseconds before the first retry and at most 2 seconds between retries.
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Send a request with a buffered body, retrying it according to the policy.
+pub(crate) async fn send_with_retries(
+    transport: Transport,
+    policy: RetryPolicy,
+    req: Request<Body>,
+) -> Result<Response<Incoming>, BoxError> {
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await?.to_bytes();
+
+    let mut retry = 0;
+    loop {
+        let result = transport.send(rebuild_request(&parts, &body)).await;
+        let retryable = match &result {
+            Ok(res) => res.status().is_server_error(),
+            Err(_) => true,
+        };
+        if !retryable || retry >= policy.max_retries {
+            return result;
+        }
+
+        let delay = policy.backoff(retry);
+        retry += 1;
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(res) => tracing::warn!(
+                uri = %parts.uri,
+                status = %res.status(),
+                retry,
+                max_retries = policy.max_retries,
+                ?delay,
+                "Runtime API request failed, retrying"
+            ),
+            Err(err) => tracing::warn!(
+                uri = %parts.uri,
+                error = %err,
+                retry,
+                max_retries = policy.max_retries,
+                ?delay,
+                "Runtime API request failed, retrying"
+            ),
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn rebuild_request(parts: &Parts, body: &Bytes) -> Request<Body> {
+    let mut req = Request::new(Body::from(body.clone()));
+    *req.method_mut() = parts.method.clone();
+    *req.uri_mut() = parts.uri.clone();
+    *req.version_mut() = parts.version;
+    *req.headers_mut() = parts.headers.clone();
+    req
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+    use http_body_util::Full;
+    use hyper::{server::conn::http1, service::service_fn};
+    use hyper_util::rt::TokioIo;
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    /// Transport that serves every request with an in-memory HTTP server, which responds
+    /// with `status` and counts the requests it receives.
+    fn status_transport(status: StatusCode, requests: Arc<AtomicUsize>) -> Transport {
+        Transport::from_service(tower::service_fn(move |req: Request<Body>| {
+            let requests = requests.clone();
+            async move {
+                let (client, server) = tokio::io::duplex(64 * 1024);
+                let service = service_fn(move |_: Request<Incoming>| {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    let mut res = Response::new(Full::new(Bytes::new()));
+                    *res.status_mut() = status;
+                    async move { Ok::<_, Infallible>(res) }
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(server), service));
+
+                let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(client)).await?;
+                tokio::spawn(conn);
+                Ok::<_, BoxError>(sender.send_request(req).await?)
+            }
+        }))
+    }
+
+    fn request(method: http::Method, path: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(format!("http://localhost:9001{path}"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() -> Result<(), BoxError> {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let transport = status_transport(StatusCode::SERVICE_UNAVAILABLE, requests.clone());
+        let policy = RetryPolicy::new(2).with_initial_backoff(Duration::from_millis(1));
+
+        let req = request(http::Method::GET, "/2018-06-01/runtime/invocation/next");
+        let res = send_with_retries(transport, policy, req).await?;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        assert_eq!(3, requests.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() -> Result<(), BoxError> {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let transport = status_transport(StatusCode::BAD_REQUEST, requests.clone());
+        let policy = RetryPolicy::new(2).with_initial_backoff(Duration::from_millis(1));
+
+        let req = request(http::Method::POST, "/2018-06-01/runtime/invocation/id/error");
+        let res = send_with_retries(transport, policy, req).await?;
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy::new(10)
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_millis(500));
+
+        assert_eq!(Duration::from_millis(100), policy.backoff(0));
+        assert_eq!(Duration::from_millis(200), policy.backoff(1));
+        assert_eq!(Duration::from_millis(400), policy.backoff(2));
+        assert_eq!(Duration::from_millis(500), policy.backoff(3));
+        assert_eq!(Duration::from_millis(500), policy.backoff(40));
+    }
+
+    #[test]
+    fn rebuild_request_keeps_parts() {
+        let req = Request::post("http://localhost:9001/2018-06-01/runtime/invocation/id/response")
+            .header("User-Agent", "test")
+            .body(())
+            .unwrap();
+        let (parts, _) = req.into_parts();
+
+        let rebuilt = rebuild_request(&parts, &Bytes::from_static(b"{}"));
+        assert_eq!(parts.method, rebuilt.method());
+        assert_eq!(parts.uri, *rebuilt.uri());
+        assert_eq!("test", rebuilt.headers()["User-Agent"]);
+    }
+}
@@ -5,9 +5,18 @@
 //! so you don't have to include them as direct dependencies in
 //! your projects.
 
-use std::{env, str::FromStr};
+use std::{env, fmt, str::FromStr};
 
-use subscriber::filter::{EnvFilter, LevelFilter};
+use serde_json::{Map, Value as JsonValue};
+use subscriber::{
+    filter::{EnvFilter, LevelFilter},
+    fmt::{
+        format::{JsonFields, Writer},
+        time::{FormatTime, SystemTime},
+        FmtContext, FormatEvent, FormattedFields,
+    },
+    registry::LookupSpan,
+};
 /// Re-export the `tracing` crate to have access to tracing macros
 /// like `info!`, `debug!`, `trace!` and so on.
 pub use tracing::*;
@@ -28,12 +37,12 @@ const DEFAULT_LOG_LEVEL: &str = "INFO";
 ///     - if none of those two variables are set, use `INFO` as the logging level.
 ///
 /// The logging format can also be changed based on Lambda's advanced logging controls.
-/// If the `AWS_LAMBDA_LOG_FORMAT` environment variable is set to `JSON`, the log lines will be formatted as json objects,
-/// otherwise they will be formatted with the default tracing format.
+/// If the `AWS_LAMBDA_LOG_FORMAT` environment variable is set to `JSON`, the log lines will be formatted
+/// with [LambdaJsonFormat], otherwise they will be formatted with the default tracing format.
 pub fn init_default_subscriber() {
     let log_format = env::var("AWS_LAMBDA_LOG_FORMAT").unwrap_or_default();
     let log_level_str = env::var("AWS_LAMBDA_LOG_LEVEL").or_else(|_| env::var("RUST_LOG"));
-    let log_level = Level::from_str(log_level_str.as_deref().unwrap_or(DEFAULT_LOG_LEVEL)).unwrap_or(Level::INFO);
+    let log_level = parse_log_level(log_level_str.as_deref().unwrap_or(DEFAULT_LOG_LEVEL)).unwrap_or(Level::INFO);
 
     let collector = tracing_subscriber::fmt()
         .with_target(false)
@@ -45,8 +54,202 @@ pub fn init_default_subscriber() {
         );
 
     if log_format.eq_ignore_ascii_case("json") {
-        collector.json().init()
+        collector
+            .fmt_fields(JsonFields::new())
+            .event_format(LambdaJsonFormat::new())
+            .init()
     } else {
         collector.init()
     }
 }
+
+/// Parse a log level, accepting the `FATAL` level of Lambda's advanced logging controls
+/// as an alias of `ERROR`.
+fn parse_log_level(level: &str) -> Option<Level> {
+    if level.eq_ignore_ascii_case("fatal") {
+        return Some(Level::ERROR);
+    }
+    Level::from_str(level).ok()
+}
+
+/// Event formatter that writes log lines as JSON objects following the schema of
+/// [Lambda's advanced logging controls](https://docs.aws.amazon.com/lambda/latest/dg/monitoring-cloudwatchlogs.html#monitoring-cloudwatchlogs-JSON).
+///
+/// Every line has the `timestamp`, `level` and `message` fields at the top level, like the
+/// logs of the managed runtimes. The fields of the spans that enclose the event are lifted
+/// to the top level too, so the `requestId` recorded by the runtime's invocation span is
+/// present in every line, and its `xrayTraceId` is reported as `traceId`.
+///
+/// This formatter reads the span fields recorded with [JsonFields], so both must be
+/// configured together:
+///
+/// ```no_run
+/// use lambda_runtime_api_client::tracing::{
+///     subscriber::fmt::format::JsonFields,
+///     LambdaJsonFormat,
+/// };
+///
+/// tracing_subscriber::fmt()
+///     .fmt_fields(JsonFields::new())
+///     .event_format(LambdaJsonFormat::new())
+///     .init();
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LambdaJsonFormat {
+    _priv: (),
+}
+
+impl LambdaJsonFormat {
+    /// Create a new Lambda JSON formatter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> FormatEvent<S, JsonFields> for LambdaJsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let mut line = Map::new();
+        line.insert("timestamp".into(), JsonValue::String(timestamp));
+        line.insert("level".into(), JsonValue::String(event.metadata().level().to_string()));
+
+        // Outer spans first, so the fields of inner spans take precedence.
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<JsonFields>>() else {
+                    continue;
+                };
+                let Ok(JsonValue::Object(fields)) = serde_json::from_str::<JsonValue>(fields) else {
+                    continue;
+                };
+                for (name, value) in fields {
+                    line.insert(lambda_field_name(name), value);
+                }
+            }
+        }
+
+        let mut visitor = FieldVisitor(&mut line);
+        event.record(&mut visitor);
+        line.entry("message")
+            .or_insert_with(|| JsonValue::String(String::new()));
+
+        let line = serde_json::to_string(&line).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{line}")
+    }
+}
+
+/// Rename the span fields recorded by the runtime to their name in Lambda's log schema.
+fn lambda_field_name(name: String) -> String {
+    match name.as_str() {
+        "xrayTraceId" => "traceId".into(),
+        _ => name,
+    }
+}
+
+/// Visitor that records the fields of an event as JSON values.
+struct FieldVisitor<'a>(&'a mut Map<String, JsonValue>);
+
+impl field::Visit for FieldVisitor<'_> {
+    fn record_f64(&mut self, field: &field::Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &field::Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &field::Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &field::Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &field::Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &field::Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().into(), format!("{value:?}").into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log_line(f: impl FnOnce()) -> JsonValue {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .fmt_fields(JsonFields::new())
+            .event_format(LambdaJsonFormat::new())
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+
+        let output = buffer.0.lock().unwrap().clone();
+        serde_json::from_slice(&output).unwrap()
+    }
+
+    #[test]
+    fn invocation_fields_are_lifted_to_the_top_level() {
+        let line = log_line(|| {
+            let span = info_span!("Lambda runtime invoke", requestId = "req-1", xrayTraceId = "Root=1-abc");
+            let _guard = span.enter();
+            info!(order = 42, "order placed");
+        });
+
+        assert_eq!("INFO", line["level"]);
+        assert_eq!("order placed", line["message"]);
+        assert_eq!("req-1", line["requestId"]);
+        assert_eq!("Root=1-abc", line["traceId"]);
+        assert_eq!(42, line["order"]);
+        assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn events_outside_invocations_have_no_request_id() {
+        let line = log_line(|| warn!("cold start"));
+
+        assert_eq!("WARN", line["level"]);
+        assert_eq!("cold start", line["message"]);
+        assert!(line.get("requestId").is_none());
+    }
+
+    #[test]
+    fn fatal_is_an_alias_of_error() {
+        assert_eq!(Some(Level::ERROR), parse_log_level("FATAL"));
+        assert_eq!(Some(Level::DEBUG), parse_log_level("debug"));
+        assert_eq!(None, parse_log_level("verbose"));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::{body::Body, BoxError};
+use futures_util::{future::BoxFuture, FutureExt, TryFutureExt};
+use http::{Request, Response};
+use hyper::body::Incoming;
+use hyper_util::{client::legacy::connect::Connect, rt::TokioExecutor};
+use std::{fmt, sync::Arc};
+use tower::{Service, ServiceExt};
+
+type SendRequest = dyn Fn(Request<Body>) -> BoxFuture<'static, Result<Response<Incoming>, BoxError>> + Send + Sync;
+
+/// Transport used by a [Client](crate::Client) to send requests to the Runtime API.
+#[derive(Clone)]
+pub(crate) struct Transport(Arc<SendRequest>);
+
+impl Transport {
+    /// Create a transport that sends requests with a `hyper` client over the given connector.
+    pub(crate) fn from_connector<C>(connector: C) -> Self
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        let client = hyper_util::client::legacy::Client::builder(TokioExecutor::new())
+            .http1_max_buf_size(1024 * 1024)
+            .build(connector);
+        Self::from_service(client)
+    }
+
+    /// Create a transport that sends requests with a tower service.
+    pub(crate) fn from_service<S>(service: S) -> Self
+    where
+        S: Service<Request<Body>, Response = Response<Incoming>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        Self(Arc::new(move |req| {
+            service.clone().oneshot(req).map_err(Into::into).boxed()
+        }))
+    }
+
+    pub(crate) fn send(&self, req: Request<Body>) -> BoxFuture<'static, Result<Response<Incoming>, BoxError>> {
+        (self.0)(req)
+    }
+}
+
+impl fmt::Debug for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transport").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{deserializer, Diagnostic, Error};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, fmt::Debug};
+
+pub use crate::deserializer::DeserializeError;
+
+/// Decode the payload of incoming events into values of type `T`.
+///
+/// Implement this trait, along with [Encode], to plug a different payload format
+/// into a [Runtime](crate::Runtime) with [Runtime::new_with_codec](crate::Runtime::new_with_codec).
+pub trait Decode<T> {
+    /// Error returned when the payload cannot be decoded.
+    ///
+    /// The error is reported to the Lambda Runtime API as an invocation error,
+    /// and the handler is not called.
+    type Error: for<'a> Into<Diagnostic<'a>> + Debug;
+
+    /// Decode the raw payload of an event.
+    fn decode(&self, payload: Bytes) -> Result<T, Self::Error>;
+}
+
+/// Encode the buffered responses of a function, of type `T`, into the raw bytes
+/// sent to the Lambda Runtime API.
+///
+/// Streaming responses are sent as they are, and never go through the codec.
+pub trait Encode<T> {
+    /// Encode a buffered response.
+    fn encode(&self, response: T) -> Result<Bytes, Error>;
+}
+
+/// Codec that decodes payloads and encodes responses as JSON with `serde_json`.
+///
+/// This is the codec used by [crate::run] and [Runtime::new](crate::Runtime::new).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+impl<T> Decode<T> for JsonCodec
+where
+    T: for<'de> Deserialize<'de>,
+{
+    type Error = DeserializeError;
+
+    fn decode(&self, payload: Bytes) -> Result<T, Self::Error> {
+        deserializer::deserialize(&payload)
+    }
+}
+
+impl<T> Encode<T> for JsonCodec
+where
+    T: Serialize,
+{
+    fn encode(&self, response: T) -> Result<Bytes, Error> {
+        Ok(serde_json::to_vec(&response)?.into())
+    }
+}
+
+/// Codec that passes payloads and responses through as raw [Bytes], without parsing them.
+///
+/// # Example
+/// ```no_run
+/// use bytes::Bytes;
+/// use lambda_runtime::{codec::RawCodec, Error, LambdaEvent, Runtime};
+/// use tower::service_fn;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     Runtime::new_with_codec(service_fn(echo), RawCodec).run().await
+/// }
+///
+/// async fn echo(event: LambdaEvent<Bytes>) -> Result<Bytes, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawCodec;
+
+impl Decode<Bytes> for RawCodec {
+    type Error = Infallible;
+
+    fn decode(&self, payload: Bytes) -> Result<Bytes, Self::Error> {
+        Ok(payload)
+    }
+}
+
+impl Encode<Bytes> for RawCodec {
+    fn encode(&self, response: Bytes) -> Result<Bytes, Error> {
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn json_codec_decodes_payloads() {
+        let value: Value = JsonCodec.decode(Bytes::from_static(b"{\"hello\":\"world\"}")).unwrap();
+        assert_eq!(json!({"hello": "world"}), value);
+    }
+
+    #[test]
+    fn json_codec_reports_decode_errors() {
+        let result: Result<u32, _> = JsonCodec.decode(Bytes::from_static(b"\"not a number\""));
+        let diagnostic: Diagnostic<'_> = result.unwrap_err().into();
+        assert_eq!("lambda_runtime::deserializer::DeserializeError", diagnostic.error_type);
+    }
+
+    #[test]
+    fn json_codec_encodes_responses() {
+        let body = JsonCodec.encode(json!({"hello": "world"})).unwrap();
+        assert_eq!(Bytes::from_static(b"{\"hello\":\"world\"}"), body);
+    }
+
+    #[test]
+    fn raw_codec_passes_bytes_through() {
+        let payload = Bytes::from_static(b"\x00not json\xff");
+        let decoded = RawCodec.decode(payload.clone()).unwrap();
+        assert_eq!(payload, decoded);
+        assert_eq!(payload, RawCodec.encode(decoded).unwrap());
+    }
+}
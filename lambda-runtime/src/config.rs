@@ -0,0 +1,418 @@
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::{env, fmt, path::PathBuf};
+use tracing::warn;
+
+/// Configuration derived from environment variables.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Config {
+    /// The name of the function.
+    pub function_name: String,
+    /// The amount of memory available to the function in MB.
+    pub memory: i32,
+    /// The version of the function being executed.
+    pub version: String,
+    /// The name of the Amazon CloudWatch Logs stream for the function.
+    pub log_stream: String,
+    /// The name of the Amazon CloudWatch Logs group for the function.
+    pub log_group: String,
+    /// The AWS Region where the function is executed.
+    pub region: Option<String>,
+    /// How the execution environment of the function was initialized.
+    #[serde(default)]
+    pub initialization_type: InitializationType,
+    /// The runtime identifier, like `AWS_Lambda_provided.al2023`.
+    pub execution_env: Option<String>,
+    /// The handler location configured for the function.
+    pub handler: Option<String>,
+    /// The path to the code of the function.
+    pub task_root: Option<PathBuf>,
+    /// The format of the function logs, configured with Lambda's advanced logging controls.
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// The minimum level of the function logs, configured with Lambda's advanced logging controls.
+    pub log_level: Option<LogLevel>,
+    /// Whether the execution environment was restored from a Lambda SnapStart snapshot.
+    ///
+    /// The runtime sets this flag once the restore phase completes, so it's only `true`
+    /// in the configuration passed to invocations after a restore.
+    #[serde(default)]
+    pub restored: bool,
+}
+
+impl Config {
+    /// Attempts to read configuration from environment variables.
+    ///
+    /// # Panics
+    /// This function panics if a required environment variable is missing or invalid.
+    /// Use [Config::try_from_env] to handle those errors.
+    pub fn from_env() -> Self {
+        Self::try_from_env().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Attempts to read configuration from environment variables,
+    /// returning an error if a required variable is missing or invalid.
+    pub fn try_from_env() -> Result<Self, RuntimeConfigError> {
+        let function_name = required_env_var("AWS_LAMBDA_FUNCTION_NAME")?;
+        let memory = required_env_var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE")?;
+        let memory = memory.parse::<i32>().map_err(|_| RuntimeConfigError::InvalidEnvVar {
+            name: "AWS_LAMBDA_FUNCTION_MEMORY_SIZE",
+            value: memory.clone(),
+        })?;
+        Ok(Config {
+            function_name,
+            memory,
+            version: required_env_var("AWS_LAMBDA_FUNCTION_VERSION")?,
+            log_stream: env::var("AWS_LAMBDA_LOG_STREAM_NAME").unwrap_or_default(),
+            log_group: env::var("AWS_LAMBDA_LOG_GROUP_NAME").unwrap_or_default(),
+            region: env::var("AWS_REGION").ok(),
+            initialization_type: parsed_env_var("AWS_LAMBDA_INITIALIZATION_TYPE", InitializationType::parse)
+                .unwrap_or_default(),
+            execution_env: env::var("AWS_EXECUTION_ENV").ok(),
+            handler: env::var("_HANDLER").ok(),
+            task_root: env::var_os("LAMBDA_TASK_ROOT").map(PathBuf::from),
+            log_format: parsed_env_var("AWS_LAMBDA_LOG_FORMAT", LogFormat::parse).unwrap_or_default(),
+            log_level: parsed_env_var("AWS_LAMBDA_LOG_LEVEL", LogLevel::parse),
+            restored: false,
+        })
+    }
+
+    /// Create a builder to construct a [Config] explicitly, for example in tests.
+    ///
+    /// # Example
+    /// ```
+    /// use lambda_runtime::{Config, InitializationType};
+    ///
+    /// let config = Config::builder()
+    ///     .with_function_name("my-function")
+    ///     .with_memory(512)
+    ///     .with_initialization_type(InitializationType::SnapStart)
+    ///     .build();
+    /// assert_eq!("my-function", config.function_name);
+    /// ```
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+fn required_env_var(name: &'static str) -> Result<String, RuntimeConfigError> {
+    env::var(name).map_err(|_| RuntimeConfigError::MissingEnvVar(name))
+}
+
+/// Parse an optional environment variable.
+///
+/// Values that cannot be parsed are logged and ignored, so a value introduced
+/// by Lambda after this version of the runtime doesn't stop the function from starting.
+fn parsed_env_var<T>(name: &'static str, parse: fn(&str) -> Option<T>) -> Option<T> {
+    let value = env::var(name).ok().filter(|value| !value.is_empty())?;
+    let parsed = parse(&value);
+    if parsed.is_none() {
+        warn!(name, value, "ignoring unknown value for environment variable");
+    }
+    parsed
+}
+
+/// How the execution environment of a function was initialized,
+/// as reported by the `AWS_LAMBDA_INITIALIZATION_TYPE` environment variable.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum InitializationType {
+    /// The execution environment was initialized to process an incoming invocation.
+    #[default]
+    OnDemand,
+    /// The execution environment was initialized ahead of time with provisioned concurrency.
+    ProvisionedConcurrency,
+    /// The execution environment was restored from a Lambda SnapStart snapshot.
+    SnapStart,
+}
+
+impl InitializationType {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "on-demand" => Some(Self::OnDemand),
+            "provisioned-concurrency" => Some(Self::ProvisionedConcurrency),
+            "snap-start" => Some(Self::SnapStart),
+            _ => None,
+        }
+    }
+}
+
+/// Format of the function logs, as reported by the `AWS_LAMBDA_LOG_FORMAT` environment variable.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum LogFormat {
+    /// Logs are plain text lines.
+    #[default]
+    Text,
+    /// Logs are JSON objects.
+    #[serde(rename = "JSON")]
+    Json,
+}
+
+impl LogFormat {
+    fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("json") {
+            Some(Self::Json)
+        } else if value.eq_ignore_ascii_case("text") {
+            Some(Self::Text)
+        } else {
+            None
+        }
+    }
+}
+
+/// Minimum level of the function logs, as reported by the `AWS_LAMBDA_LOG_LEVEL` environment variable.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+#[non_exhaustive]
+pub enum LogLevel {
+    /// Fine-grained details about the execution of the function.
+    Trace,
+    /// Information to debug the function.
+    Debug,
+    /// Information about the normal operation of the function.
+    Info,
+    /// Potential problems that don't stop the function.
+    Warn,
+    /// Problems that prevent the function from completing an operation.
+    Error,
+    /// Problems that prevent the function from working at all.
+    Fatal,
+}
+
+impl LogLevel {
+    fn parse(value: &str) -> Option<Self> {
+        [
+            Self::Trace,
+            Self::Debug,
+            Self::Info,
+            Self::Warn,
+            Self::Error,
+            Self::Fatal,
+        ]
+        .into_iter()
+        .find(|level| level.as_str().eq_ignore_ascii_case(value))
+    }
+
+    /// The name of the level, as Lambda writes it in the environment.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trace => "TRACE",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR",
+            Self::Fatal => "FATAL",
+        }
+    }
+}
+
+/// Builder to construct a [Config] explicitly, without reading environment variables.
+///
+/// Fields that aren't set keep their default values.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Set the name of the function.
+    pub fn with_function_name(mut self, function_name: impl Into<String>) -> Self {
+        self.config.function_name = function_name.into();
+        self
+    }
+
+    /// Set the amount of memory available to the function in MB.
+    pub fn with_memory(mut self, memory: i32) -> Self {
+        self.config.memory = memory;
+        self
+    }
+
+    /// Set the version of the function.
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.config.version = version.into();
+        self
+    }
+
+    /// Set the name of the Amazon CloudWatch Logs stream for the function.
+    pub fn with_log_stream(mut self, log_stream: impl Into<String>) -> Self {
+        self.config.log_stream = log_stream.into();
+        self
+    }
+
+    /// Set the name of the Amazon CloudWatch Logs group for the function.
+    pub fn with_log_group(mut self, log_group: impl Into<String>) -> Self {
+        self.config.log_group = log_group.into();
+        self
+    }
+
+    /// Set the AWS Region where the function is executed.
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.config.region = Some(region.into());
+        self
+    }
+
+    /// Set how the execution environment was initialized.
+    pub fn with_initialization_type(mut self, initialization_type: InitializationType) -> Self {
+        self.config.initialization_type = initialization_type;
+        self
+    }
+
+    /// Set the runtime identifier.
+    pub fn with_execution_env(mut self, execution_env: impl Into<String>) -> Self {
+        self.config.execution_env = Some(execution_env.into());
+        self
+    }
+
+    /// Set the handler location of the function.
+    pub fn with_handler(mut self, handler: impl Into<String>) -> Self {
+        self.config.handler = Some(handler.into());
+        self
+    }
+
+    /// Set the path to the code of the function.
+    pub fn with_task_root(mut self, task_root: impl Into<PathBuf>) -> Self {
+        self.config.task_root = Some(task_root.into());
+        self
+    }
+
+    /// Set the format of the function logs.
+    pub fn with_log_format(mut self, log_format: LogFormat) -> Self {
+        self.config.log_format = log_format;
+        self
+    }
+
+    /// Set the minimum level of the function logs.
+    pub fn with_log_level(mut self, log_level: LogLevel) -> Self {
+        self.config.log_level = Some(log_level);
+        self
+    }
+
+    /// Set whether the execution environment was restored from a SnapStart snapshot.
+    pub fn with_restored(mut self, restored: bool) -> Self {
+        self.config.restored = restored;
+        self
+    }
+
+    /// Build the [Config].
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+/// Error returned when the runtime cannot be configured from its environment.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RuntimeConfigError {
+    /// A required environment variable is not set, or it's not valid unicode.
+    MissingEnvVar(&'static str),
+    /// An environment variable is set to a value that cannot be parsed.
+    InvalidEnvVar {
+        /// The name of the environment variable.
+        name: &'static str,
+        /// The value of the environment variable.
+        value: String,
+    },
+    /// The client to interact with the Lambda Runtime API cannot be created.
+    Client(Error),
+}
+
+impl fmt::Display for RuntimeConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeConfigError::MissingEnvVar(name) => write!(f, "missing {name} env var"),
+            RuntimeConfigError::InvalidEnvVar { name, value } => write!(f, "invalid value for {name} env var: {value}"),
+            RuntimeConfigError::Client(err) => write!(f, "unable to create a runtime client: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RuntimeConfigError::Client(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_initialization_types() {
+        assert_eq!(
+            Some(InitializationType::OnDemand),
+            InitializationType::parse("on-demand")
+        );
+        assert_eq!(
+            Some(InitializationType::ProvisionedConcurrency),
+            InitializationType::parse("provisioned-concurrency")
+        );
+        assert_eq!(
+            Some(InitializationType::SnapStart),
+            InitializationType::parse("snap-start")
+        );
+        assert_eq!(None, InitializationType::parse("eventually"));
+    }
+
+    #[test]
+    fn parse_log_formats() {
+        assert_eq!(Some(LogFormat::Json), LogFormat::parse("JSON"));
+        assert_eq!(Some(LogFormat::Text), LogFormat::parse("Text"));
+        assert_eq!(None, LogFormat::parse("xml"));
+    }
+
+    #[test]
+    fn parse_log_levels() {
+        assert_eq!(Some(LogLevel::Debug), LogLevel::parse("DEBUG"));
+        assert_eq!(Some(LogLevel::Warn), LogLevel::parse("warn"));
+        assert_eq!(None, LogLevel::parse("LOUD"));
+    }
+
+    #[test]
+    fn unknown_env_var_values_are_ignored() {
+        env::set_var("TEST_PARSED_ENV_VAR_UNKNOWN", "LOUD");
+        assert_eq!(None, parsed_env_var("TEST_PARSED_ENV_VAR_UNKNOWN", LogLevel::parse));
+
+        env::set_var("TEST_PARSED_ENV_VAR_KNOWN", "DEBUG");
+        assert_eq!(
+            Some(LogLevel::Debug),
+            parsed_env_var("TEST_PARSED_ENV_VAR_KNOWN", LogLevel::parse)
+        );
+    }
+
+    #[test]
+    fn builder_sets_fields() {
+        let config = Config::builder()
+            .with_function_name("test-function")
+            .with_memory(256)
+            .with_region("eu-west-1")
+            .with_task_root("/var/task")
+            .with_log_format(LogFormat::Json)
+            .with_log_level(LogLevel::Info)
+            .build();
+
+        assert_eq!("test-function", config.function_name);
+        assert_eq!(256, config.memory);
+        assert_eq!(Some("eu-west-1".to_string()), config.region);
+        assert_eq!(Some(PathBuf::from("/var/task")), config.task_root);
+        assert_eq!(InitializationType::OnDemand, config.initialization_type);
+        assert_eq!(LogFormat::Json, config.log_format);
+        assert_eq!(Some(LogLevel::Info), config.log_level);
+    }
+
+    #[test]
+    fn deserialize_config_without_new_fields() {
+        let config: Config = serde_json::from_str(
+            r#"{"function_name":"f","memory":128,"version":"1","log_stream":"s","log_group":"g"}"#,
+        )
+        .unwrap();
+        assert_eq!(InitializationType::OnDemand, config.initialization_type);
+        assert_eq!(LogFormat::Text, config.log_format);
+        assert_eq!(None, config.log_level);
+    }
+}
@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::{any::type_name, borrow::Cow};
+use std::{
+    any::type_name,
+    backtrace::{Backtrace, BacktraceStatus},
+    borrow::Cow,
+};
 
 use crate::{deserializer::DeserializeError, Error};
 
@@ -14,20 +18,26 @@ use crate::{deserializer::DeserializeError, Error};
 /// You can define your own error container that implements `Into<Diagnostic>`
 /// if you need to handle errors based on error types.
 ///
+/// When a `Diagnostic` is derived from a type that implements [`Error`][std::error::Error],
+/// its [`cause`][`Diagnostic::cause`] chain is filled with the messages of the errors returned
+/// by [`source`][std::error::Error::source]. The type of those errors is not known, so their
+/// [`error_type`][`Diagnostic::error_type`] is left empty. Its
+/// [`stack_trace`][`Diagnostic::stack_trace`] is filled with a [`Backtrace`] when backtraces
+/// are enabled with the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variables.
+///
+/// `Diagnostic` can gain new fields, so build it with [`Diagnostic::new`] and the `with_*`
+/// methods rather than with a struct literal.
+///
 /// Example:
 /// ```
 /// use lambda_runtime::{Diagnostic, Error, LambdaEvent};
-/// use std::borrow::Cow;
 ///
 /// #[derive(Debug)]
 /// struct ErrorResponse(Error);
 ///
 /// impl<'a> Into<Diagnostic<'a>> for ErrorResponse {
 ///     fn into(self) -> Diagnostic<'a> {
-///         Diagnostic {
-///             error_type: "MyError".into(),
-///             error_message: self.0.to_string().into(),
-///         }
+///         Diagnostic::new("MyError", self.0.to_string())
 ///     }
 /// }
 ///
@@ -36,7 +46,8 @@ use crate::{deserializer::DeserializeError, Error};
 ///    Ok(())
 /// }
 /// ```
-#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Diagnostic<'a> {
     /// Error type.
@@ -51,44 +62,289 @@ pub struct Diagnostic<'a> {
     /// `error_message` is the output from the [`Display`][std::fmt::Display]
     /// implementation of the original error as a fallback.
     pub error_message: Cow<'a, str>,
+    /// Stack trace.
+    ///
+    /// `stack_trace` contains the lines of a [`Backtrace`] captured when the
+    /// original error is converted, if backtraces are enabled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stack_trace: Vec<String>,
+    /// Underlying cause of the error.
+    ///
+    /// `cause` is derived from the [`source`][std::error::Error::source]
+    /// of the original error, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cause: Option<Box<Diagnostic<'a>>>,
+    /// Exception data attached to the X-Ray segment of the invocation.
+    ///
+    /// `xray_cause` is sent to the Lambda Runtime API in the
+    /// `Lambda-Runtime-Function-XRay-Error-Cause` header when the invocation
+    /// has an X-Ray trace id. If it's not set, the runtime derives it from
+    /// the other fields of the diagnostic.
+    #[serde(skip)]
+    pub xray_cause: Option<XRayErrorCause>,
 }
 
-impl<'a> From<DeserializeError> for Diagnostic<'a> {
-    fn from(value: DeserializeError) -> Self {
+impl<'a> Diagnostic<'a> {
+    /// Create a new `Diagnostic` with the given error type and message.
+    pub fn new(error_type: impl Into<Cow<'a, str>>, error_message: impl Into<Cow<'a, str>>) -> Self {
         Diagnostic {
-            error_type: type_name::<DeserializeError>().into(),
-            error_message: value.to_string().into(),
+            error_type: error_type.into(),
+            error_message: error_message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the stack trace of the error, one frame or location per line.
+    pub fn with_stack_trace(self, stack_trace: Vec<String>) -> Self {
+        Diagnostic { stack_trace, ..self }
+    }
+
+    /// Set the underlying cause of the error.
+    pub fn with_cause(self, cause: Diagnostic<'a>) -> Self {
+        Diagnostic {
+            cause: Some(Box::new(cause)),
+            ..self
+        }
+    }
+
+    /// Set the exception data attached to the X-Ray segment of the invocation.
+    pub fn with_xray_cause(self, xray_cause: XRayErrorCause) -> Self {
+        Diagnostic {
+            xray_cause: Some(xray_cause),
+            ..self
+        }
+    }
+
+    fn from_error<E>(error_type: &'static str, error: &E) -> Self
+    where
+        E: std::error::Error + ?Sized,
+    {
+        Diagnostic {
+            error_type: error_type.into(),
+            error_message: error.to_string().into(),
+            stack_trace: stack_trace_lines(Backtrace::capture()),
+            cause: cause_chain(error.source()),
+            xray_cause: None,
+        }
+    }
+}
+
+fn cause_chain<'a>(source: Option<&(dyn std::error::Error + 'static)>) -> Option<Box<Diagnostic<'a>>> {
+    source.map(|source| {
+        Box::new(Diagnostic {
+            error_type: Cow::Borrowed(""),
+            error_message: source.to_string().into(),
+            stack_trace: Vec::new(),
+            cause: cause_chain(source.source()),
+            xray_cause: None,
+        })
+    })
+}
+
+fn stack_trace_lines(backtrace: Backtrace) -> Vec<String> {
+    match backtrace.status() {
+        BacktraceStatus::Captured => backtrace
+            .to_string()
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Maximum size of the `Lambda-Runtime-Function-XRay-Error-Cause` header accepted by the Lambda Runtime API.
+const XRAY_ERROR_CAUSE_MAX_SIZE: usize = 1024 * 1024;
+
+/// Error cause reported to AWS X-Ray when an invocation fails.
+///
+/// The Lambda Runtime API attaches this information to the X-Ray segment
+/// of the invocation as exception data.
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct XRayErrorCause {
+    /// The working directory of the function.
+    pub working_directory: String,
+    /// The exceptions that caused the error, from the outermost to the innermost.
+    pub exceptions: Vec<XRayException>,
+    /// The paths of the source files involved in the error.
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+/// Exception data in an [`XRayErrorCause`].
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct XRayException {
+    /// The type of the exception.
+    #[serde(rename = "type")]
+    pub error_type: String,
+    /// The exception message.
+    pub message: String,
+    /// The stack frames of the exception, from the innermost to the outermost.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stack: Vec<XRayStackFrame>,
+}
+
+/// Stack frame of an [`XRayException`].
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct XRayStackFrame {
+    /// The path of the source file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The line number in the source file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// The function or method name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl XRayErrorCause {
+    /// Serialize the error cause into a header value, shrinking it to fit in the size
+    /// accepted by the Lambda Runtime API. Stack frames are dropped first, then the nested
+    /// exceptions. Returns `None` if the error cause still doesn't fit.
+    pub(crate) fn to_header_value(&self) -> Option<String> {
+        let fits = |cause: &XRayErrorCause| {
+            serde_json::to_string(cause)
+                .ok()
+                .filter(|value| value.len() <= XRAY_ERROR_CAUSE_MAX_SIZE)
+        };
+
+        if let Some(value) = fits(self) {
+            return Some(value);
+        }
+
+        let mut cause = self.clone();
+        cause.paths.clear();
+        cause
+            .exceptions
+            .iter_mut()
+            .for_each(|exception| exception.stack.clear());
+        if let Some(value) = fits(&cause) {
+            return Some(value);
+        }
+
+        cause.exceptions.truncate(1);
+        fits(&cause)
+    }
+}
+
+impl<'a> From<&Diagnostic<'a>> for XRayErrorCause {
+    fn from(value: &Diagnostic<'a>) -> Self {
+        let working_directory = std::env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+
+        let mut exceptions = Vec::new();
+        let mut next = Some(value);
+        while let Some(diagnostic) = next {
+            exceptions.push(XRayException {
+                error_type: diagnostic.error_type.to_string(),
+                message: diagnostic.error_message.to_string(),
+                stack: stack_frames(&diagnostic.stack_trace),
+            });
+            next = diagnostic.cause.as_deref();
         }
+
+        let mut paths: Vec<String> = exceptions
+            .iter()
+            .flat_map(|exception| exception.stack.iter().filter_map(|frame| frame.path.clone()))
+            .collect();
+        paths.sort();
+        paths.dedup();
+
+        XRayErrorCause {
+            working_directory,
+            exceptions,
+            paths,
+        }
+    }
+}
+
+/// Parse the lines of a [`Backtrace`] into X-Ray stack frames.
+///
+/// Backtrace lines alternate between frame labels, like `3: my_function::handler`,
+/// and source locations, like `at ./src/main.rs:10:5`.
+fn stack_frames(stack_trace: &[String]) -> Vec<XRayStackFrame> {
+    let mut frames: Vec<XRayStackFrame> = Vec::new();
+    for line in stack_trace {
+        match line.strip_prefix("at ") {
+            Some(location) => {
+                let mut parts = location.rsplitn(3, ':');
+                let (_column, line, path) = (parts.next(), parts.next(), parts.next());
+                let (path, line) = match (path, line.and_then(|line| line.parse().ok())) {
+                    (Some(path), Some(line)) => (path.to_string(), Some(line)),
+                    _ => (location.to_string(), None),
+                };
+                match frames.last_mut() {
+                    Some(frame) if frame.path.is_none() => {
+                        frame.path = Some(path);
+                        frame.line = line;
+                    }
+                    _ => frames.push(XRayStackFrame {
+                        path: Some(path),
+                        line,
+                        label: None,
+                    }),
+                }
+            }
+            None => {
+                let label = match line.split_once(": ") {
+                    Some((index, label)) if index.chars().all(|c| c.is_ascii_digit()) => label,
+                    _ => line.as_str(),
+                };
+                frames.push(XRayStackFrame {
+                    label: Some(label.to_string()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    frames
+}
+
+impl<'a> std::fmt::Display for Diagnostic<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.error_type, self.error_message)
+    }
+}
+
+/// A `Diagnostic` can be used as an error, for example to fail a streaming response with
+/// a specific error type. The runtime recovers the original `Diagnostic` when it reports
+/// the error.
+impl<'a> std::error::Error for Diagnostic<'a> {}
+
+impl<'a> From<DeserializeError> for Diagnostic<'a> {
+    fn from(value: DeserializeError) -> Self {
+        Diagnostic::from_error(type_name::<DeserializeError>(), &value)
     }
 }
 
 impl<'a> From<Error> for Diagnostic<'a> {
     fn from(value: Error) -> Self {
-        Diagnostic {
-            error_type: type_name::<Error>().into(),
-            error_message: value.to_string().into(),
+        match value.downcast::<Diagnostic<'static>>() {
+            Ok(diagnostic) => *diagnostic,
+            Err(value) => Diagnostic::from_error(type_name::<Error>(), &*value),
         }
     }
 }
 
+impl<'a> From<lambda_runtime_api_client::Error> for Diagnostic<'a> {
+    fn from(value: lambda_runtime_api_client::Error) -> Self {
+        value.into_inner().into()
+    }
+}
+
 impl<'a, T> From<Box<T>> for Diagnostic<'a>
 where
     T: std::error::Error,
 {
     fn from(value: Box<T>) -> Self {
-        Diagnostic {
-            error_type: type_name::<T>().into(),
-            error_message: value.to_string().into(),
-        }
+        Diagnostic::from_error(type_name::<T>(), &*value)
     }
 }
 
 impl<'a> From<Box<dyn std::error::Error>> for Diagnostic<'a> {
     fn from(value: Box<dyn std::error::Error>) -> Self {
-        Diagnostic {
-            error_type: type_name::<Box<dyn std::error::Error>>().into(),
-            error_message: value.to_string().into(),
-        }
+        Diagnostic::from_error(type_name::<Box<dyn std::error::Error>>(), &*value)
     }
 }
 
@@ -97,6 +353,7 @@ impl<'a> From<std::convert::Infallible> for Diagnostic<'a> {
         Diagnostic {
             error_type: type_name::<std::convert::Infallible>().into(),
             error_message: value.to_string().into(),
+            ..Default::default()
         }
     }
 }
@@ -106,6 +363,7 @@ impl<'a> From<String> for Diagnostic<'a> {
         Diagnostic {
             error_type: type_name::<String>().into(),
             error_message: value.into(),
+            ..Default::default()
         }
     }
 }
@@ -115,6 +373,7 @@ impl<'a> From<&'static str> for Diagnostic<'a> {
         Diagnostic {
             error_type: type_name::<&'static str>().into(),
             error_message: value.into(),
+            ..Default::default()
         }
     }
 }
@@ -134,8 +393,145 @@ mod test {
         let actual = Diagnostic {
             error_type: "InvalidEventDataError".into(),
             error_message: "Error parsing event data.".into(),
+            ..Default::default()
         };
         let actual: Value = serde_json::to_value(actual).expect("failed to serialize diagnostic");
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn round_trip_lambda_error_with_stack_trace_and_cause() {
+        use serde_json::{json, Value};
+        let expected = json!({
+            "errorType": "InvalidEventDataError",
+            "errorMessage": "Error parsing event data.",
+            "stackTrace": ["0: lambda_runtime::handler"],
+            "cause": {
+                "errorType": "SyntaxError",
+                "errorMessage": "expected value",
+            },
+        });
+
+        let actual = Diagnostic::new("InvalidEventDataError", "Error parsing event data.")
+            .with_stack_trace(vec!["0: lambda_runtime::handler".to_string()])
+            .with_cause(Diagnostic::new("SyntaxError", "expected value"));
+        let actual: Value = serde_json::to_value(actual).expect("failed to serialize diagnostic");
+        assert_eq!(expected, actual);
+
+        let parsed: Diagnostic<'_> = serde_json::from_value(expected).expect("failed to deserialize diagnostic");
+        assert_eq!(parsed.cause.unwrap().error_message, "expected value");
+    }
+
+    #[test]
+    fn boxed_error_includes_source_chain() {
+        #[derive(Debug)]
+        struct Outer(std::io::Error);
+
+        impl std::fmt::Display for Outer {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("failed to load configuration")
+            }
+        }
+
+        impl std::error::Error for Outer {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let err: Error = Box::new(Outer(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "config.json not found",
+        )));
+        let diagnostic: Diagnostic<'_> = err.into();
+
+        assert_eq!(diagnostic.error_message, "failed to load configuration");
+        let cause = diagnostic.cause.expect("missing error cause");
+        assert_eq!(cause.error_type, "");
+        assert_eq!(cause.error_message, "config.json not found");
+        assert!(cause.cause.is_none());
+    }
+
+    #[test]
+    fn xray_error_cause_from_diagnostic() {
+        let diagnostic = Diagnostic {
+            error_type: "InvalidEventDataError".into(),
+            error_message: "Error parsing event data.".into(),
+            stack_trace: vec![
+                "0: my_function::handler".to_string(),
+                "at ./src/main.rs:10:5".to_string(),
+                "1: tokio::runtime::task::harness::poll".to_string(),
+            ],
+            cause: Some(Box::new(Diagnostic {
+                error_type: "SyntaxError".into(),
+                error_message: "expected value".into(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let cause = XRayErrorCause::from(&diagnostic);
+        assert_eq!(cause.exceptions.len(), 2);
+        assert_eq!(cause.exceptions[0].error_type, "InvalidEventDataError");
+        assert_eq!(
+            cause.exceptions[0].stack,
+            vec![
+                XRayStackFrame {
+                    path: Some("./src/main.rs".to_string()),
+                    line: Some(10),
+                    label: Some("my_function::handler".to_string()),
+                },
+                XRayStackFrame {
+                    label: Some("tokio::runtime::task::harness::poll".to_string()),
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(cause.exceptions[1].message, "expected value");
+        assert_eq!(cause.paths, vec!["./src/main.rs".to_string()]);
+    }
+
+    #[test]
+    fn xray_error_cause_is_truncated() {
+        let frame = XRayStackFrame {
+            path: Some("./src/main.rs".to_string()),
+            line: Some(10),
+            label: Some("x".repeat(1024)),
+        };
+        let exception = XRayException {
+            error_type: "InvalidEventDataError".to_string(),
+            message: "Error parsing event data.".to_string(),
+            stack: vec![frame; 1024],
+        };
+        let cause = XRayErrorCause {
+            working_directory: "/var/task".to_string(),
+            exceptions: vec![exception.clone(), exception],
+            paths: vec!["./src/main.rs".to_string()],
+        };
+
+        let value = cause.to_header_value().expect("missing header value");
+        assert!(value.len() <= XRAY_ERROR_CAUSE_MAX_SIZE);
+
+        let truncated: XRayErrorCause = serde_json::from_str(&value).unwrap();
+        assert_eq!(truncated.exceptions.len(), 2);
+        assert!(truncated.exceptions.iter().all(|exception| exception.stack.is_empty()));
+    }
+
+    #[test]
+    fn boxed_diagnostic_is_recovered() {
+        let diagnostic = Diagnostic::new("UpstreamTimeout", "model stopped responding");
+        let err: Error = diagnostic.clone().into();
+        assert_eq!(diagnostic, Diagnostic::from(err));
+    }
+
+    #[test]
+    fn stack_trace_is_captured_when_enabled() {
+        let lines = stack_trace_lines(Backtrace::force_capture());
+        assert!(!lines.is_empty());
+        assert!(lines
+            .iter()
+            .any(|line| line.contains("stack_trace_is_captured_when_enabled")));
+
+        assert!(stack_trace_lines(Backtrace::disabled()).is_empty());
+    }
 }
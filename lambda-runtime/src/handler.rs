@@ -0,0 +1,176 @@
+use crate::LambdaEvent;
+use futures::future::BoxFuture;
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::Service;
+
+/// A Lambda function handler with access to shared state.
+///
+/// Use this trait instead of a [tower::Service] when the handler needs resources
+/// that are initialized once and reused across invocations, like SDK clients or
+/// connection pools. The state is owned by the runtime and lent to every invocation.
+///
+/// Handlers are turned into services with [StateService], or passed directly to
+/// [Runtime::with_state](crate::Runtime::with_state). Implementors return the
+/// future of [Handler::handle] boxed, usually with `Box::pin(async move { ... })`.
+///
+/// # Example
+/// ```no_run
+/// use futures::future::BoxFuture;
+/// use lambda_runtime::{Error, Handler, LambdaEvent, Runtime};
+/// use serde_json::{json, Value};
+///
+/// struct Clients {
+///     table_name: String,
+/// }
+///
+/// struct PutItem;
+///
+/// impl Handler<Clients> for PutItem {
+///     type Event = Value;
+///     type Response = Value;
+///     type Error = Error;
+///
+///     fn handle<'a>(&'a self, state: &'a Clients, event: LambdaEvent<Value>) -> BoxFuture<'a, Result<Value, Error>> {
+///         Box::pin(async move { Ok(json!({ "table": state.table_name, "item": event.payload })) })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let state = Clients {
+///         table_name: std::env::var("TABLE_NAME")?,
+///     };
+///     Runtime::with_state(state, PutItem).run().await
+/// }
+/// ```
+pub trait Handler<State> {
+    /// The payload of the events handled.
+    type Event;
+    /// The response of the handler.
+    type Response;
+    /// The error returned when the handler fails.
+    type Error;
+
+    /// Handle an event, with access to the shared state.
+    fn handle<'a>(
+        &'a self,
+        state: &'a State,
+        event: LambdaEvent<Self::Event>,
+    ) -> BoxFuture<'a, Result<Self::Response, Self::Error>>;
+}
+
+/// Tower service that calls a [Handler] with its shared state.
+///
+/// This adapter plugs a [Handler] into any place that accepts a
+/// `Service<LambdaEvent<T>>`, like [Runtime::new](crate::Runtime::new) or [crate::run],
+/// and composes with other tower layers.
+pub struct StateService<H, State> {
+    handler: Arc<H>,
+    state: Arc<State>,
+}
+
+impl<H, State> StateService<H, State> {
+    /// Create a new service that calls `handler` with `state`.
+    pub fn new(handler: H, state: State) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            state: Arc::new(state),
+        }
+    }
+}
+
+impl<H, State> Clone for StateService<H, State> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<H, State> Service<LambdaEvent<H::Event>> for StateService<H, State>
+where
+    H: Handler<State> + Send + Sync + 'static,
+    H::Event: Send + 'static,
+    State: Send + Sync + 'static,
+{
+    type Response = H::Response;
+    type Error = H::Error;
+    type Future = BoxFuture<'static, Result<H::Response, H::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, event: LambdaEvent<H::Event>) -> Self::Future {
+        let handler = self.handler.clone();
+        let state = self.state.clone();
+        Box::pin(async move { handler.handle(&state, event).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{FakeRuntimeApi, TestEvent, TestOutcome},
+        Context as LambdaContext, Error,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    struct Counter;
+
+    impl Handler<AtomicUsize> for Counter {
+        type Event = String;
+        type Response = String;
+        type Error = Error;
+
+        fn handle<'a>(
+            &'a self,
+            state: &'a AtomicUsize,
+            event: LambdaEvent<String>,
+        ) -> BoxFuture<'a, Result<String, Error>> {
+            Box::pin(async move {
+                let count = state.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok(format!("{} #{count}", event.payload))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn state_is_shared_between_invocations() {
+        let service = StateService::new(Counter, AtomicUsize::new(0));
+
+        let event = || LambdaEvent::new("hello".to_string(), LambdaContext::default());
+        let first = service.clone().oneshot(event()).await.unwrap();
+        let second = service.clone().oneshot(event()).await.unwrap();
+
+        assert_eq!("hello #1", first);
+        assert_eq!("hello #2", second);
+        assert_eq!(2, service.state.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn state_handler_runs_in_the_runtime() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::json(&"hello")?.with_request_id("first"));
+        api.enqueue(TestEvent::json(&"hello")?.with_request_id("second"));
+
+        let runtime = api.runtime(StateService::new(Counter, AtomicUsize::new(0)));
+        let invocations = api.run(runtime).await?;
+        assert_eq!(invocations.len(), 2);
+
+        for (request_id, expected) in [("first", r#""hello #1""#), ("second", r#""hello #2""#)] {
+            let invocation = invocations.iter().find(|i| i.request_id == request_id).unwrap();
+            match &invocation.outcome {
+                TestOutcome::Response(body) => assert_eq!(&body[..], expected.as_bytes()),
+                outcome => panic!("unexpected outcome: {outcome:?}"),
+            }
+        }
+        Ok(())
+    }
+}
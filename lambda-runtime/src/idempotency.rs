@@ -0,0 +1,493 @@
+use crate::{Diagnostic, Error, LambdaEvent};
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex, PoisonError},
+    task,
+    time::{Duration, SystemTime},
+};
+use tower::{Layer, Service};
+use tracing::error;
+
+const DEFAULT_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+/// State of an idempotency key in an [IdempotencyStore].
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdempotencyRecord {
+    /// An invocation with the same key is being processed.
+    InProgress,
+    /// An invocation with the same key completed with the given response.
+    Completed(Value),
+}
+
+/// Storage for the idempotency records of a function.
+///
+/// Implement this trait to keep the records in a persistent backend, like a DynamoDB table,
+/// so they are shared by all the execution environments of the function. [InMemoryStore]
+/// keeps the records in the memory of the current execution environment.
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Lock the key for an invocation that is about to be processed.
+    ///
+    /// If a record that has not expired yet exists for the key, the store must return it and
+    /// leave it unchanged. Otherwise, the store must save an [IdempotencyRecord::InProgress]
+    /// record that expires at `expires_at`, and return `None`. Persistent stores should perform
+    /// this check and write atomically, with a conditional write for example.
+    fn acquire<'a>(
+        &'a self,
+        key: &'a str,
+        expires_at: SystemTime,
+    ) -> BoxFuture<'a, Result<Option<IdempotencyRecord>, Error>>;
+
+    /// Save the response of a completed invocation, replacing the in progress record.
+    fn complete<'a>(
+        &'a self,
+        key: &'a str,
+        response: Value,
+        expires_at: SystemTime,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Delete the in progress record of an invocation that failed, so it can be retried.
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+/// [IdempotencyStore] that keeps the records in memory.
+///
+/// Records are not shared between execution environments, so this store only protects against
+/// duplicate invocations that reach the same execution environment. Expired records are removed
+/// every time a key is acquired or completed.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    records: Mutex<HashMap<String, (IdempotencyRecord, SystemTime)>>,
+}
+
+impl InMemoryStore {
+    /// Create a new empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdempotencyStore for InMemoryStore {
+    fn acquire<'a>(
+        &'a self,
+        key: &'a str,
+        expires_at: SystemTime,
+    ) -> BoxFuture<'a, Result<Option<IdempotencyRecord>, Error>> {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = SystemTime::now();
+        records.retain(|_, (_, record_expires_at)| *record_expires_at > now);
+        let existing = match records.get(key) {
+            Some((record, _)) => Some(record.clone()),
+            None => {
+                records.insert(key.to_string(), (IdempotencyRecord::InProgress, expires_at));
+                None
+            }
+        };
+        future::ready(Ok(existing)).boxed()
+    }
+
+    fn complete<'a>(
+        &'a self,
+        key: &'a str,
+        response: Value,
+        expires_at: SystemTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = SystemTime::now();
+        records.retain(|_, (_, record_expires_at)| *record_expires_at > now);
+        records.insert(key.to_string(), (IdempotencyRecord::Completed(response), expires_at));
+        future::ready(Ok(())).boxed()
+    }
+
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        records.remove(key);
+        future::ready(Ok(())).boxed()
+    }
+}
+
+/// Error returned by an [IdempotencyService].
+#[derive(Debug)]
+pub enum IdempotencyError<E> {
+    /// The handler returned an error.
+    Handler(E),
+    /// Another invocation with the same idempotency key is being processed.
+    AlreadyInProgress(String),
+    /// The idempotency store failed, or the stored response cannot be converted back.
+    Store(Error),
+}
+
+impl<E: fmt::Display> fmt::Display for IdempotencyError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdempotencyError::Handler(err) => err.fmt(f),
+            IdempotencyError::AlreadyInProgress(key) => {
+                write!(f, "an invocation with the idempotency key {key} is already in progress")
+            }
+            IdempotencyError::Store(err) => write!(f, "idempotency store error: {err}"),
+        }
+    }
+}
+
+impl<'a, E> From<IdempotencyError<E>> for Diagnostic<'a>
+where
+    E: Into<Diagnostic<'a>>,
+{
+    fn from(value: IdempotencyError<E>) -> Self {
+        match value {
+            IdempotencyError::Handler(err) => err.into(),
+            IdempotencyError::AlreadyInProgress(key) => Diagnostic {
+                error_type: Cow::Borrowed("IdempotencyAlreadyInProgressError"),
+                error_message: Cow::Owned(format!(
+                    "an invocation with the idempotency key {key} is already in progress"
+                )),
+                ..Default::default()
+            },
+            IdempotencyError::Store(err) => Diagnostic {
+                error_type: Cow::Borrowed("IdempotencyStoreError"),
+                error_message: Cow::Owned(err.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Tower layer that makes a handler idempotent.
+///
+/// The layer extracts an idempotency key from the payload of every event. The first invocation
+/// with a given key calls the handler and saves its response in the [IdempotencyStore]. Later
+/// invocations with the same key return the saved response without calling the handler, until
+/// the record expires. While an invocation is in progress, the key is locked until the deadline
+/// of the invocation, and concurrent invocations with the same key fail with
+/// [IdempotencyError::AlreadyInProgress]. If the handler fails or panics, the key is released so
+/// the invocation can be retried.
+///
+/// Events without a key are passed to the handler without any idempotency check.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{
+///     idempotency::{IdempotencyLayer, InMemoryStore},
+///     Error, LambdaEvent, Runtime,
+/// };
+/// use serde_json::{json, Value};
+/// use tower::{service_fn, Layer};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let layer = IdempotencyLayer::new(InMemoryStore::new(), |payload: &Value| {
+///         payload["orderId"].as_str().map(String::from)
+///     });
+///     Runtime::new(layer.layer(service_fn(place_order))).run().await
+/// }
+///
+/// async fn place_order(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(json!({ "placed": event.payload["orderId"] }))
+/// }
+/// ```
+pub struct IdempotencyLayer<St, K> {
+    store: Arc<St>,
+    key_fn: K,
+    expiry: Duration,
+}
+
+impl<St, K> IdempotencyLayer<St, K>
+where
+    St: IdempotencyStore,
+{
+    /// Create a new [IdempotencyLayer] that saves its records in `store`, and extracts the
+    /// idempotency key of the events with `key_fn`.
+    pub fn new(store: St, key_fn: K) -> Self {
+        Self {
+            store: Arc::new(store),
+            key_fn,
+            expiry: DEFAULT_EXPIRY,
+        }
+    }
+
+    /// Set how long the response of a completed invocation is replayed. The default is one hour.
+    pub fn with_expiry(self, expiry: Duration) -> Self {
+        Self { expiry, ..self }
+    }
+}
+
+impl<S, St, K> Layer<S> for IdempotencyLayer<St, K>
+where
+    K: Clone,
+{
+    type Service = IdempotencyService<S, St, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IdempotencyService {
+            inner,
+            store: self.store.clone(),
+            key_fn: self.key_fn.clone(),
+            expiry: self.expiry,
+        }
+    }
+}
+
+/// Tower service created by [IdempotencyLayer].
+pub struct IdempotencyService<S, St, K> {
+    inner: S,
+    store: Arc<St>,
+    key_fn: K,
+    expiry: Duration,
+}
+
+impl<S, St, K> Clone for IdempotencyService<S, St, K>
+where
+    S: Clone,
+    K: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            key_fn: self.key_fn.clone(),
+            expiry: self.expiry,
+        }
+    }
+}
+
+impl<S, St, K, T> Service<LambdaEvent<T>> for IdempotencyService<S, St, K>
+where
+    S: Service<LambdaEvent<T>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Response: Serialize + DeserializeOwned + Send + 'static,
+    S::Error: Send + 'static,
+    St: IdempotencyStore,
+    K: Fn(&T) -> Option<String>,
+    T: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = IdempotencyError<S::Error>;
+    type Future = BoxFuture<'static, Result<S::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(IdempotencyError::Handler)
+    }
+
+    fn call(&mut self, event: LambdaEvent<T>) -> Self::Future {
+        let key = match (self.key_fn)(&event.payload) {
+            Some(key) => format!("{}#{}", event.context.env_config.function_name, key),
+            None => {
+                return self
+                    .inner
+                    .call(event)
+                    .map(|result| result.map_err(IdempotencyError::Handler))
+                    .boxed()
+            }
+        };
+
+        // Take the service that was driven to readiness, and leave a clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let store = self.store.clone();
+        let expiry = self.expiry;
+
+        async move {
+            let in_progress_expiry = event.context.deadline();
+            match store.acquire(&key, in_progress_expiry).await {
+                Ok(None) => {}
+                Ok(Some(IdempotencyRecord::Completed(response))) => {
+                    return serde_json::from_value(response).map_err(|err| IdempotencyError::Store(err.into()));
+                }
+                Ok(Some(IdempotencyRecord::InProgress)) => return Err(IdempotencyError::AlreadyInProgress(key)),
+                Err(err) => return Err(IdempotencyError::Store(err)),
+            }
+
+            let result = AssertUnwindSafe(async { inner.call(event).await }).catch_unwind().await;
+            let response = match result {
+                Ok(Ok(response)) => response,
+                Ok(Err(err)) => {
+                    release(store.as_ref(), &key).await;
+                    return Err(IdempotencyError::Handler(err));
+                }
+                Err(panic) => {
+                    // Release the key before the panic reaches the runtime, which reports it
+                    // as an invocation error.
+                    release(store.as_ref(), &key).await;
+                    std::panic::resume_unwind(panic);
+                }
+            };
+
+            // The handler already ran, so failing to save its response doesn't fail the invocation.
+            let saved = match serde_json::to_value(&response) {
+                Ok(value) => store.complete(&key, value, SystemTime::now() + expiry).await,
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = saved {
+                error!(error = %err, key = %key, "failed to save idempotent response");
+            }
+            Ok(response)
+        }
+        .boxed()
+    }
+}
+
+async fn release<St: IdempotencyStore>(store: &St, key: &str) {
+    if let Err(err) = store.release(key).await {
+        error!(error = %err, key = %key, "failed to release idempotency key");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+    use serde_json::json;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::UNIX_EPOCH,
+    };
+
+    fn event(payload: Value) -> LambdaEvent<Value> {
+        let deadline = SystemTime::now() + Duration::from_secs(60);
+        let context = Context {
+            deadline: deadline.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+            ..Default::default()
+        };
+        LambdaEvent::new(payload, context)
+    }
+
+    fn order_id(payload: &Value) -> Option<String> {
+        payload["orderId"].as_str().map(String::from)
+    }
+
+    #[tokio::test]
+    async fn completed_responses_are_replayed() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        let handler = tower::service_fn(move |event: LambdaEvent<Value>| {
+            let calls = handler_calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { Ok::<_, Error>(json!({ "order": event.payload["orderId"], "calls": calls })) }
+        });
+        let mut service = IdempotencyLayer::new(InMemoryStore::new(), order_id).layer(handler);
+
+        let first = service.call(event(json!({ "orderId": "1" }))).await.unwrap();
+        let replayed = service.call(event(json!({ "orderId": "1" }))).await.unwrap();
+        let other = service.call(event(json!({ "orderId": "2" }))).await.unwrap();
+
+        assert_eq!(json!({ "order": "1", "calls": 1 }), first);
+        assert_eq!(first, replayed);
+        assert_eq!(json!({ "order": "2", "calls": 2 }), other);
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn events_without_key_are_not_tracked() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        let handler = tower::service_fn(move |_: LambdaEvent<Value>| {
+            handler_calls.fetch_add(1, Ordering::SeqCst);
+            async { Ok::<_, Error>(json!({})) }
+        });
+        let mut service = IdempotencyLayer::new(InMemoryStore::new(), order_id).layer(handler);
+
+        service.call(event(json!({}))).await.unwrap();
+        service.call(event(json!({}))).await.unwrap();
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn failed_invocations_release_the_key() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        let handler = tower::service_fn(move |_: LambdaEvent<Value>| {
+            let calls = handler_calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                if calls == 1 {
+                    Err(Error::from("temporary failure"))
+                } else {
+                    Ok(json!({ "calls": calls }))
+                }
+            }
+        });
+        let mut service = IdempotencyLayer::new(InMemoryStore::new(), order_id).layer(handler);
+
+        let err = service.call(event(json!({ "orderId": "1" }))).await.unwrap_err();
+        assert!(matches!(err, IdempotencyError::Handler(_)));
+
+        let retried = service.call(event(json!({ "orderId": "1" }))).await.unwrap();
+        assert_eq!(json!({ "calls": 2 }), retried);
+    }
+
+    #[tokio::test]
+    async fn panicked_invocations_release_the_key() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        let handler = tower::service_fn(move |_: LambdaEvent<Value>| {
+            let calls = handler_calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                if calls == 1 {
+                    panic!("handler panicked");
+                }
+                Ok::<_, Error>(json!({ "calls": calls }))
+            }
+        });
+        let mut service = IdempotencyLayer::new(InMemoryStore::new(), order_id).layer(handler);
+
+        let panicked = AssertUnwindSafe(service.call(event(json!({ "orderId": "1" }))))
+            .catch_unwind()
+            .await;
+        assert!(panicked.is_err());
+
+        let retried = service.call(event(json!({ "orderId": "1" }))).await.unwrap();
+        assert_eq!(json!({ "calls": 2 }), retried);
+    }
+
+    #[tokio::test]
+    async fn expired_records_are_removed() {
+        let store = InMemoryStore::new();
+        let expired = SystemTime::now() - Duration::from_secs(1);
+        store.acquire("expired", expired).await.unwrap();
+
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        store.acquire("active", expires_at).await.unwrap();
+
+        let records = store.records.lock().unwrap();
+        assert!(!records.contains_key("expired"));
+        assert!(records.contains_key("active"));
+    }
+
+    #[tokio::test]
+    async fn in_progress_keys_are_rejected() {
+        let store = InMemoryStore::new();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        store.acquire("#1", expires_at).await.unwrap();
+
+        let handler = tower::service_fn(|_: LambdaEvent<Value>| async { Ok::<_, Error>(json!({})) });
+        let mut service = IdempotencyLayer::new(store, order_id).layer(handler);
+
+        let err = service.call(event(json!({ "orderId": "1" }))).await.unwrap_err();
+        let diagnostic: Diagnostic<'_> = err.into();
+        assert_eq!("IdempotencyAlreadyInProgressError", diagnostic.error_type);
+    }
+
+    #[tokio::test]
+    async fn expired_records_are_replaced() {
+        let store = InMemoryStore::new();
+        let expired = SystemTime::now() - Duration::from_secs(1);
+        store.complete("key", json!("old"), expired).await.unwrap();
+
+        let acquired = store
+            .acquire("key", SystemTime::now() + Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(None, acquired);
+        let acquired = store
+            .acquire("key", SystemTime::now() + Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(Some(IdempotencyRecord::InProgress), acquired);
+    }
+}
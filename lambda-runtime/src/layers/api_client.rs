@@ -1,11 +1,11 @@
-use crate::LambdaInvocation;
+use crate::{requests::InvocationErrorReport, InvocationTimings, LambdaInvocation};
 use futures::{future::BoxFuture, ready, FutureExt, TryFutureExt};
 use hyper::body::Incoming;
-use lambda_runtime_api_client::{body::Body, BoxError, Client};
+use lambda_runtime_api_client::{body::Body, BoxError, CallLatency, Client};
 use pin_project::pin_project;
-use std::{future::Future, pin::Pin, sync::Arc, task};
+use std::{future::Future, pin::Pin, sync::Arc, task, time::Instant};
 use tower::Service;
-use tracing::error;
+use tracing::{debug, error};
 
 /// Tower service that sends a Lambda Runtime API response to the Lambda Runtime HTTP API using
 /// a previously initialized client.
@@ -22,6 +22,26 @@ impl<S> RuntimeApiClientService<S> {
     pub fn new(inner: S, client: Arc<Client>) -> Self {
         Self { inner, client }
     }
+
+    pub(crate) fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    pub(crate) fn set_client(&mut self, client: Arc<Client>) {
+        self.client = client;
+    }
+}
+
+impl<S> Clone for RuntimeApiClientService<S>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            client: self.client.clone(),
+        }
+    }
 }
 
 impl<S> Service<LambdaInvocation> for RuntimeApiClientService<S>
@@ -38,16 +58,43 @@ where
     }
 
     fn call(&mut self, req: LambdaInvocation) -> Self::Future {
+        let timings = req.timings.clone();
+        let start = Instant::now();
         let request_fut = self.inner.call(req);
         let client = self.client.clone();
-        RuntimeApiClientFuture::First(request_fut, client)
+        RuntimeApiClientFuture::First(request_fut, client, timings, start)
     }
 }
 
 #[pin_project(project = RuntimeApiClientFutureProj)]
 pub enum RuntimeApiClientFuture<F> {
-    First(#[pin] F, Arc<Client>),
-    Second(#[pin] BoxFuture<'static, Result<http::Response<Incoming>, BoxError>>),
+    First(#[pin] F, Arc<Client>, InvocationTimings, Instant),
+    Second(
+        #[pin] BoxFuture<'static, Result<http::Response<Incoming>, BoxError>>,
+        InvocationTimings,
+        bool,
+    ),
+}
+
+/// Record the latency of the call that completed the invocation, and emit all the
+/// timings of the invocation as `tracing` fields.
+fn record_timings(timings: &InvocationTimings, res: &http::Response<Incoming>, is_error: bool) {
+    if let Some(CallLatency(latency)) = res.extensions().get() {
+        if is_error {
+            timings.set_error_report(*latency);
+        } else {
+            timings.set_response_upload(*latency);
+        }
+    }
+    debug!(
+        next_event_ms = timings.next_event().as_secs_f64() * 1000.0,
+        payload_download_ms = timings.payload_download().as_secs_f64() * 1000.0,
+        handler_ms = timings.handler().map(|d| d.as_secs_f64() * 1000.0),
+        response_upload_ms = timings.response_upload().map(|d| d.as_secs_f64() * 1000.0),
+        error_report_ms = timings.error_report().map(|d| d.as_secs_f64() * 1000.0),
+        overhead_ms = timings.overhead().as_secs_f64() * 1000.0,
+        "invocation completed"
+    );
 }
 
 impl<F> Future for RuntimeApiClientFuture<F>
@@ -60,8 +107,11 @@ where
         // NOTE: We loop here to directly poll the second future once the first has finished.
         task::Poll::Ready(loop {
             match self.as_mut().project() {
-                RuntimeApiClientFutureProj::First(fut, client) => match ready!(fut.poll(cx)) {
+                RuntimeApiClientFutureProj::First(fut, client, timings, start) => match ready!(fut.poll(cx)) {
                     Ok(ok) => {
+                        timings.set_handler(start.elapsed());
+                        let is_error = ok.extensions().get::<InvocationErrorReport>().is_some();
+                        let timings = timings.clone();
                         // NOTE: We use 'client.call_boxed' here to obtain a future with static
                         // lifetime. Otherwise, this future would need to be self-referential...
                         let next_fut = client
@@ -71,11 +121,13 @@ where
                                 err
                             })
                             .boxed();
-                        self.set(RuntimeApiClientFuture::Second(next_fut));
+                        self.set(RuntimeApiClientFuture::Second(next_fut, timings, is_error));
                     }
                     Err(err) => break Err(err),
                 },
-                RuntimeApiClientFutureProj::Second(fut) => break ready!(fut.poll(cx)).map(|_| ()),
+                RuntimeApiClientFutureProj::Second(fut, timings, is_error) => {
+                    break ready!(fut.poll(cx)).map(|res| record_timings(timings, &res, *is_error))
+                }
             }
         })
     }
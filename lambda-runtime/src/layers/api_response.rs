@@ -1,13 +1,12 @@
 use crate::{
-    deserializer,
+    codec::{Decode, Encode, JsonCodec},
     requests::{EventCompletionRequest, IntoRequest},
     runtime::LambdaInvocation,
-    Diagnostic, EventErrorRequest, IntoFunctionResponse, LambdaEvent,
+    Diagnostic, EventErrorRequest, IntoFunctionResponse, LambdaEvent, ResponseSizePolicy,
 };
 use futures::{ready, Stream};
 use lambda_runtime_api_client::{body::Body, BoxError};
 use pin_project::pin_project;
-use serde::{Deserialize, Serialize};
 use std::{fmt::Debug, future::Future, marker::PhantomData, pin::Pin, task};
 use tower::Service;
 use tracing::{error, trace};
@@ -28,8 +27,11 @@ pub struct RuntimeApiResponseService<
     StreamingResponse,
     StreamItem,
     StreamError,
+    Codec = JsonCodec,
 > {
     inner: S,
+    codec: Codec,
+    response_size_policy: ResponseSizePolicy,
     _phantom: PhantomData<(
         EventPayload,
         Response,
@@ -40,18 +42,58 @@ pub struct RuntimeApiResponseService<
     )>,
 }
 
-impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
-    RuntimeApiResponseService<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
+impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, Codec>
+    RuntimeApiResponseService<
+        S,
+        EventPayload,
+        Response,
+        BufferedResponse,
+        StreamingResponse,
+        StreamItem,
+        StreamError,
+        Codec,
+    >
 {
-    pub fn new(inner: S) -> Self {
+    pub fn new(inner: S, codec: Codec) -> Self {
         Self {
             inner,
+            codec,
+            response_size_policy: ResponseSizePolicy::default(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub(crate) fn set_response_size_policy(&mut self, response_size_policy: ResponseSizePolicy) {
+        self.response_size_policy = response_size_policy;
+    }
+}
+
+impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, Codec> Clone
+    for RuntimeApiResponseService<
+        S,
+        EventPayload,
+        Response,
+        BufferedResponse,
+        StreamingResponse,
+        StreamItem,
+        StreamError,
+        Codec,
+    >
+where
+    S: Clone,
+    Codec: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            response_size_policy: self.response_size_policy,
             _phantom: PhantomData,
         }
     }
 }
 
-impl<'a, S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
+impl<'a, S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, Codec>
     Service<LambdaInvocation>
     for RuntimeApiResponseService<
         S,
@@ -61,20 +103,28 @@ impl<'a, S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamI
         StreamingResponse,
         StreamItem,
         StreamError,
+        Codec,
     >
 where
     S: Service<LambdaEvent<EventPayload>, Response = Response, Error = Diagnostic<'a>>,
-    EventPayload: for<'de> Deserialize<'de>,
     Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
-    BufferedResponse: Serialize,
+    Codec: Decode<EventPayload> + Encode<BufferedResponse> + Clone,
     StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
     StreamItem: Into<bytes::Bytes> + Send,
     StreamError: Into<BoxError> + Send + Debug,
 {
     type Response = http::Request<Body>;
     type Error = BoxError;
-    type Future =
-        RuntimeApiResponseFuture<'a, S::Future, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>;
+    type Future = RuntimeApiResponseFuture<
+        'a,
+        S::Future,
+        Response,
+        BufferedResponse,
+        StreamingResponse,
+        StreamItem,
+        StreamError,
+        Codec,
+    >;
 
     fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
         self.inner
@@ -103,9 +153,10 @@ where
         };
 
         let request_id = req.context.request_id.clone();
-        let lambda_event = match deserializer::deserialize::<EventPayload>(&req.body, req.context) {
-            Ok(lambda_event) => lambda_event,
-            Err(err) => match build_event_error_request(&request_id, err) {
+        let xray_trace_id = req.context.xray_trace_id.clone();
+        let lambda_event = match self.codec.decode(req.body) {
+            Ok(payload) => LambdaEvent::new(payload, req.context),
+            Err(err) => match build_event_error_request(&request_id, xray_trace_id.as_deref(), err) {
                 Ok(request) => return RuntimeApiResponseFuture::Ready(Some(Ok(request))),
                 Err(err) => {
                     error!(error = ?err, "failed to build error response for Lambda Runtime API");
@@ -116,23 +167,48 @@ where
 
         // Once the handler input has been generated successfully, the
         let fut = self.inner.call(lambda_event);
-        RuntimeApiResponseFuture::Future(fut, request_id, PhantomData)
+        RuntimeApiResponseFuture::Future(
+            fut,
+            request_id,
+            xray_trace_id,
+            self.codec.clone(),
+            self.response_size_policy,
+            PhantomData,
+        )
     }
 }
 
-fn build_event_error_request<'a, T>(request_id: &'a str, err: T) -> Result<http::Request<Body>, BoxError>
+fn build_event_error_request<'a, T>(
+    request_id: &'a str,
+    xray_trace_id: Option<&'a str>,
+    err: T,
+) -> Result<http::Request<Body>, BoxError>
 where
     T: Into<Diagnostic<'a>> + Debug,
 {
     error!(error = ?err, "building error response for Lambda Runtime API");
-    EventErrorRequest::new(request_id, err).into_req()
+    EventErrorRequest::new(request_id, err)
+        .with_xray_trace_id(xray_trace_id)
+        .into_req()
 }
 
 #[pin_project(project = RuntimeApiResponseFutureProj)]
-pub enum RuntimeApiResponseFuture<'a, F, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError> {
+pub enum RuntimeApiResponseFuture<
+    'a,
+    F,
+    Response,
+    BufferedResponse,
+    StreamingResponse,
+    StreamItem,
+    StreamError,
+    Codec = JsonCodec,
+> {
     Future(
         #[pin] F,
         String,
+        Option<String>,
+        Codec,
+        ResponseSizePolicy,
         PhantomData<(
             &'a (),
             Response,
@@ -145,12 +221,12 @@ pub enum RuntimeApiResponseFuture<'a, F, Response, BufferedResponse, StreamingRe
     Ready(Option<Result<http::Request<Body>, BoxError>>),
 }
 
-impl<'a, F, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError> Future
-    for RuntimeApiResponseFuture<'a, F, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
+impl<'a, F, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, Codec> Future
+    for RuntimeApiResponseFuture<'a, F, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, Codec>
 where
     F: Future<Output = Result<Response, Diagnostic<'a>>>,
     Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
-    BufferedResponse: Serialize,
+    Codec: Encode<BufferedResponse> + Clone,
     StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
     StreamItem: Into<bytes::Bytes> + Send,
     StreamError: Into<BoxError> + Send + Debug,
@@ -159,10 +235,17 @@ where
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
         task::Poll::Ready(match self.as_mut().project() {
-            RuntimeApiResponseFutureProj::Future(fut, request_id, _) => match ready!(fut.poll(cx)) {
-                Ok(ok) => EventCompletionRequest::new(request_id, ok).into_req(),
-                Err(err) => EventErrorRequest::new(request_id, err).into_req(),
-            },
+            RuntimeApiResponseFutureProj::Future(fut, request_id, xray_trace_id, codec, response_size_policy, _) => {
+                match ready!(fut.poll(cx)) {
+                    Ok(ok) => EventCompletionRequest::with_codec(request_id, ok, codec.clone())
+                        .with_xray_trace_id(xray_trace_id.as_deref())
+                        .with_response_size_policy(*response_size_policy)
+                        .into_req(),
+                    Err(err) => EventErrorRequest::new(request_id, err)
+                        .with_xray_trace_id(xray_trace_id.as_deref())
+                        .into_req(),
+                }
+            }
             RuntimeApiResponseFutureProj::Ready(ready) => ready.take().expect("future polled after completion"),
         })
     }
@@ -0,0 +1,319 @@
+use crate::{
+    requests::{EventErrorRequest, IntoRequest},
+    runtime::InvocationClient,
+    Diagnostic, InvocationTimings, LambdaInvocation,
+};
+use futures::{future::BoxFuture, ready, FutureExt, TryFutureExt};
+use lambda_runtime_api_client::{BoxError, Client};
+use pin_project::pin_project;
+use std::{
+    borrow::Cow,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task,
+    time::{Duration, SystemTime},
+};
+use tokio::time::Sleep;
+use tower::{Layer, Service};
+use tracing::error;
+
+const HANDLER_TIMEOUT_ERROR_TYPE: &str = "Runtime.HandlerTimeout";
+
+/// Tower layer to stop a Lambda function invocation before the Lambda service reaches its deadline.
+///
+/// The layer races the invocation against the [deadline](crate::Context::deadline) minus a safety
+/// margin. When the safety margin is reached first, the handler future is dropped, an error of
+/// type `Runtime.HandlerTimeout` is reported to the Lambda Runtime API, and the timeout hook is
+/// called. Use the hook to flush logs and telemetry before Lambda stops the execution environment.
+///
+/// The error is reported with the Runtime API client of the runtime, so it uses the same endpoint
+/// and [retry policy](crate::Runtime::with_retry_policy). Once the handler has completed and its
+/// result is being sent, the invocation is left to finish and nothing else is reported.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{layers::DeadlineLayer, Error, LambdaEvent, Runtime};
+/// use serde_json::Value;
+/// use std::time::Duration;
+/// use tower::service_fn;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let deadline = DeadlineLayer::new(Duration::from_millis(500))
+///         .with_timeout_hook(|| println!("flushing telemetry"));
+///     Runtime::new(service_fn(echo)).layer(deadline).run().await
+/// }
+///
+/// async fn echo(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+pub struct DeadlineLayer<F> {
+    safety_margin: Duration,
+    timeout_hook: F,
+}
+
+impl DeadlineLayer<fn()> {
+    /// Create a new [DeadlineLayer] that stops invocations `safety_margin` before their deadline.
+    pub fn new(safety_margin: Duration) -> Self {
+        Self {
+            safety_margin,
+            timeout_hook: || {},
+        }
+    }
+}
+
+impl<F> DeadlineLayer<F>
+where
+    F: Fn() + Clone,
+{
+    /// Set a function to call when an invocation times out, after the handler has been cancelled.
+    pub fn with_timeout_hook<H>(self, timeout_hook: H) -> DeadlineLayer<H>
+    where
+        H: Fn() + Clone,
+    {
+        DeadlineLayer {
+            safety_margin: self.safety_margin,
+            timeout_hook,
+        }
+    }
+}
+
+impl<S, F> Layer<S> for DeadlineLayer<F>
+where
+    F: Fn() + Clone,
+{
+    type Service = DeadlineService<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DeadlineService {
+            inner,
+            safety_margin: self.safety_margin,
+            timeout_hook: self.timeout_hook.clone(),
+        }
+    }
+}
+
+/// Tower service created by [DeadlineLayer].
+#[derive(Clone)]
+pub struct DeadlineService<S, F> {
+    inner: S,
+    safety_margin: Duration,
+    timeout_hook: F,
+}
+
+impl<S, F> Service<LambdaInvocation> for DeadlineService<S, F>
+where
+    S: Service<LambdaInvocation, Response = (), Error = BoxError>,
+    F: Fn() + Clone,
+{
+    type Response = ();
+    type Error = BoxError;
+    type Future = DeadlineFuture<S::Future, F>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaInvocation) -> Self::Future {
+        let remaining = req
+            .context
+            .deadline()
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .saturating_sub(self.safety_margin);
+        let request_id = req.context.request_id.clone();
+        let xray_trace_id = req.context.xray_trace_id.clone();
+        let client = req
+            .parts
+            .extensions
+            .get::<InvocationClient>()
+            .map(|InvocationClient(client)| client.clone());
+        let timings = req.timings.clone();
+
+        DeadlineFuture::Running {
+            future: self.inner.call(req),
+            sleep: tokio::time::sleep(remaining),
+            request_id,
+            xray_trace_id,
+            client,
+            timings,
+            timeout_hook: self.timeout_hook.clone(),
+        }
+    }
+}
+
+/// Future created by [DeadlineService].
+#[pin_project(project = DeadlineFutureProj)]
+pub enum DeadlineFuture<Fut, F> {
+    /// The invocation is in progress.
+    Running {
+        /// The future of the inner service.
+        #[pin]
+        future: Fut,
+        /// The timer that expires at the deadline minus the safety margin.
+        #[pin]
+        sleep: Sleep,
+        /// The id of the invocation.
+        request_id: String,
+        /// The X-Ray trace id of the invocation.
+        xray_trace_id: Option<String>,
+        /// The client to report the timeout to the Lambda Runtime API, if the invocation
+        /// was received by a runtime.
+        client: Option<Arc<Client>>,
+        /// The timings of the invocation, to know when the handler has completed.
+        timings: InvocationTimings,
+        /// The function to call when the invocation times out.
+        timeout_hook: F,
+    },
+    /// The invocation timed out, and the error is being reported to the Lambda Runtime API.
+    Reporting(#[pin] BoxFuture<'static, Result<(), BoxError>>),
+}
+
+impl<Fut, F> Future for DeadlineFuture<Fut, F>
+where
+    Fut: Future<Output = Result<(), BoxError>>,
+    F: Fn() + Clone,
+{
+    type Output = Result<(), BoxError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+        // NOTE: We loop here to directly poll the report future once the timer has expired.
+        task::Poll::Ready(loop {
+            match self.as_mut().project() {
+                DeadlineFutureProj::Running {
+                    future,
+                    sleep,
+                    request_id,
+                    xray_trace_id,
+                    client,
+                    timings,
+                    timeout_hook,
+                } => {
+                    if let task::Poll::Ready(ready) = future.poll(cx) {
+                        break ready;
+                    }
+                    // The handler has completed and its result is being sent,
+                    // so the invocation can't be reported as timed out anymore.
+                    if timings.handler().is_some() {
+                        return task::Poll::Pending;
+                    }
+                    ready!(sleep.poll(cx));
+
+                    error!(request_id = %request_id, "handler did not complete before the invocation deadline");
+                    let report = report_timeout(request_id, xray_trace_id.as_deref(), client.as_deref());
+                    let timeout_hook = timeout_hook.clone();
+
+                    // Replacing the running state drops the handler future, which cancels it.
+                    self.set(DeadlineFuture::Reporting(report));
+                    timeout_hook();
+                }
+                DeadlineFutureProj::Reporting(fut) => break ready!(fut.poll(cx)),
+            }
+        })
+    }
+}
+
+fn report_timeout(
+    request_id: &str,
+    xray_trace_id: Option<&str>,
+    client: Option<&Client>,
+) -> BoxFuture<'static, Result<(), BoxError>> {
+    let Some(client) = client else {
+        error!("the invocation has no Runtime API client to report the timeout");
+        return futures::future::ready(Err("missing Runtime API client to report the timeout".into())).boxed();
+    };
+    let diagnostic = Diagnostic {
+        error_type: Cow::Borrowed(HANDLER_TIMEOUT_ERROR_TYPE),
+        error_message: Cow::Borrowed("Handler did not complete before the invocation deadline"),
+        ..Default::default()
+    };
+    let request = EventErrorRequest::new(request_id, diagnostic).with_xray_trace_id(xray_trace_id);
+    match request.into_req() {
+        Ok(req) => client
+            .call(req)
+            .map_ok(|_| ())
+            .map_err(|err| {
+                error!(error = ?err, "failed to send timeout error to Lambda Runtime API");
+                err
+            })
+            .boxed(),
+        Err(err) => futures::future::ready(Err(err)).boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{FakeRuntimeApi, TestOutcome},
+        Context,
+    };
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const REQUEST_ID: &str = "156cb537-e2d4-11e8-9b34-d36013741fb9";
+
+    fn invocation(client: Arc<Client>, deadline: Duration) -> Result<LambdaInvocation, BoxError> {
+        let deadline = SystemTime::now() + deadline;
+        let deadline = deadline.duration_since(SystemTime::UNIX_EPOCH)?.as_millis() as u64;
+        let context = Context {
+            request_id: REQUEST_ID.to_string(),
+            deadline,
+            ..Default::default()
+        };
+        let mut parts = http::Response::new(()).into_parts().0;
+        parts.extensions.insert(InvocationClient(client));
+        Ok(LambdaInvocation::new(parts, bytes::Bytes::new(), context))
+    }
+
+    #[tokio::test]
+    async fn timed_out_invocation_is_reported() -> Result<(), BoxError> {
+        let api = FakeRuntimeApi::start().await?;
+        let client = Arc::new(api.client()?);
+
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let hook_flag = timed_out.clone();
+        let layer = DeadlineLayer::new(Duration::from_millis(50))
+            .with_timeout_hook(move || hook_flag.store(true, Ordering::SeqCst));
+
+        let handler = tower::service_fn(|_: LambdaInvocation| futures::future::pending::<Result<(), BoxError>>());
+        let mut service = layer.layer(handler);
+
+        service.call(invocation(client, Duration::from_millis(100))?).await?;
+
+        let invocations = api.take_invocations();
+        assert_eq!(REQUEST_ID, invocations[0].request_id);
+        match &invocations[0].outcome {
+            TestOutcome::Error { diagnostic, .. } => assert_eq!(HANDLER_TIMEOUT_ERROR_TYPE, diagnostic.error_type),
+            outcome => panic!("unexpected outcome: {outcome:?}"),
+        }
+        assert!(timed_out.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn completed_handler_is_not_reported() -> Result<(), BoxError> {
+        let api = FakeRuntimeApi::start().await?;
+        let client = Arc::new(api.client()?);
+
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let hook_flag = timed_out.clone();
+        let layer = DeadlineLayer::new(Duration::from_millis(50))
+            .with_timeout_hook(move || hook_flag.store(true, Ordering::SeqCst));
+
+        // The handler completes right away, and sending its response outlives the deadline.
+        let handler = tower::service_fn(|invocation: LambdaInvocation| async move {
+            invocation.timings.set_handler(Duration::from_millis(1));
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            Ok::<_, BoxError>(())
+        });
+        let mut service = layer.layer(handler);
+
+        service.call(invocation(client, Duration::from_millis(100))?).await?;
+
+        assert!(api.take_invocations().is_empty());
+        assert!(!timed_out.load(Ordering::SeqCst));
+        Ok(())
+    }
+}
@@ -4,10 +4,12 @@ mod api_response;
 mod panic;
 
 // Publicly available services.
+mod deadline;
 mod trace;
 
 pub(crate) use api_client::RuntimeApiClientService;
 pub(crate) use api_response::RuntimeApiResponseService;
+pub use deadline::{DeadlineFuture, DeadlineLayer, DeadlineService};
 pub(crate) use panic::CatchPanicService;
 pub use trace::TracingLayer;
 
@@ -1,4 +1,12 @@
-use std::{future::Future, pin::Pin, task};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task,
+};
 
 use crate::LambdaInvocation;
 use opentelemetry_semantic_conventions::trace as traceconv;
@@ -32,16 +40,19 @@ where
         OpenTelemetryService {
             inner,
             flush_fn: self.flush_fn.clone(),
-            coldstart: true,
+            coldstart: Arc::new(AtomicBool::new(true)),
         }
     }
 }
 
 /// Tower service created by [OpenTelemetryLayer].
+#[derive(Clone)]
 pub struct OpenTelemetryService<S, F> {
     inner: S,
     flush_fn: F,
-    coldstart: bool,
+    // Shared between clones, so only the first invocation of the process is a cold start
+    // when invocations are processed concurrently.
+    coldstart: Arc<AtomicBool>,
 }
 
 impl<S, F> Service<LambdaInvocation> for OpenTelemetryService<S, F>
@@ -58,17 +69,17 @@ where
     }
 
     fn call(&mut self, req: LambdaInvocation) -> Self::Future {
+        // After the first execution, we can set 'coldstart' to false
+        let coldstart = self.coldstart.swap(false, Ordering::Relaxed);
+
         let span = tracing::info_span!(
             "Lambda function invocation",
             "otel.name" = req.context.env_config.function_name,
             { traceconv::FAAS_TRIGGER } = "http",
             { traceconv::FAAS_INVOCATION_ID } = req.context.request_id,
-            { traceconv::FAAS_COLDSTART } = self.coldstart
+            { traceconv::FAAS_COLDSTART } = coldstart
         );
 
-        // After the first execution, we can set 'coldstart' to false
-        self.coldstart = false;
-
         let future = {
             // Enter the span before calling the inner service
             // to ensure that it's assigned as parent of the inner spans.
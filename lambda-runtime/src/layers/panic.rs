@@ -108,6 +108,7 @@ impl<'a, F> CatchPanicFuture<'a, F> {
         Diagnostic {
             error_type: Cow::Borrowed(error_type),
             error_message: Cow::Owned(msg),
+            ..Default::default()
         }
     }
 }
@@ -9,7 +9,6 @@
 //! and runs the Lambda runtime.
 use serde::{Deserialize, Serialize};
 use std::{
-    env,
     fmt::{self, Debug},
     future::Future,
     sync::Arc,
@@ -22,13 +21,24 @@ pub use tower::{self, service_fn, Service};
 pub mod diagnostic;
 pub use diagnostic::Diagnostic;
 
+/// Codecs to decode event payloads and encode function responses.
+pub mod codec;
+mod config;
 mod deserializer;
+mod handler;
+/// Idempotency middleware for Lambda handlers.
+pub mod idempotency;
 /// Tower middleware to be applied to runtime invocations.
 pub mod layers;
+/// CloudWatch Embedded Metric Format metrics for Lambda functions.
+pub mod metrics;
 mod requests;
 mod runtime;
 /// Utilities for Lambda Streaming functions.
 pub mod streaming;
+/// Utilities to test Lambda functions without the Lambda Runtime API.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 /// Utilities to initialize and use `tracing` and `tracing-subscriber` in Lambda Functions.
 #[cfg(feature = "tracing")]
@@ -37,46 +47,20 @@ pub use lambda_runtime_api_client::tracing;
 /// Types available to a Lambda function.
 mod types;
 
+pub use config::{Config, ConfigBuilder, InitializationType, LogFormat, LogLevel, RuntimeConfigError};
+pub use handler::{Handler, StateService};
+pub use lambda_runtime_api_client::{Client as RuntimeApiClient, RetryPolicy};
 use requests::EventErrorRequest;
-pub use runtime::{LambdaInvocation, Runtime};
-pub use types::{Context, FunctionResponse, IntoFunctionResponse, LambdaEvent, MetadataPrelude, StreamResponse};
+pub use runtime::{shutdown_signal, xray_trace_id, InvocationTimings, LambdaInvocation, Runtime};
+pub use types::{
+    Context, FunctionResponse, IntoFunctionResponse, LambdaEvent, MetadataPrelude, ResponseSizePolicy, StreamResponse,
+};
 
 /// Error type that lambdas may result in
 pub type Error = lambda_runtime_api_client::BoxError;
 
-/// Configuration derived from environment variables.
-#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
-pub struct Config {
-    /// The name of the function.
-    pub function_name: String,
-    /// The amount of memory available to the function in MB.
-    pub memory: i32,
-    /// The version of the function being executed.
-    pub version: String,
-    /// The name of the Amazon CloudWatch Logs stream for the function.
-    pub log_stream: String,
-    /// The name of the Amazon CloudWatch Logs group for the function.
-    pub log_group: String,
-}
-
 type RefConfig = Arc<Config>;
 
-impl Config {
-    /// Attempts to read configuration from environment variables.
-    pub fn from_env() -> Self {
-        Config {
-            function_name: env::var("AWS_LAMBDA_FUNCTION_NAME").expect("Missing AWS_LAMBDA_FUNCTION_NAME env var"),
-            memory: env::var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE")
-                .expect("Missing AWS_LAMBDA_FUNCTION_MEMORY_SIZE env var")
-                .parse::<i32>()
-                .expect("AWS_LAMBDA_FUNCTION_MEMORY_SIZE env var is not <i32>"),
-            version: env::var("AWS_LAMBDA_FUNCTION_VERSION").expect("Missing AWS_LAMBDA_FUNCTION_VERSION env var"),
-            log_stream: env::var("AWS_LAMBDA_LOG_STREAM_NAME").unwrap_or_default(),
-            log_group: env::var("AWS_LAMBDA_LOG_GROUP_NAME").unwrap_or_default(),
-        }
-    }
-}
-
 /// Return a new [`ServiceFn`] with a closure that takes an event and context as separate arguments.
 #[deprecated(since = "0.5.0", note = "Use `service_fn` and `LambdaEvent` instead")]
 pub fn handler_fn<A, F, Fut>(f: F) -> ServiceFn<impl Fn(LambdaEvent<A>) -> Fut>
@@ -120,6 +104,47 @@ where
     D: Into<bytes::Bytes> + Send,
     E: Into<Error> + Send + Debug,
 {
-    let runtime = Runtime::new(handler).layer(layers::TracingLayer::new());
+    let runtime = Runtime::try_new(handler)?.layer(layers::TracingLayer::new());
+    runtime.run().await
+}
+
+/// Starts the Lambda Rust runtime with a handler built by an asynchronous init function.
+///
+/// If the init function fails, its error is reported to the Lambda Runtime API as an
+/// initialization error and returned. See [Runtime::new_with_init] for more details.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{Error, service_fn, LambdaEvent};
+/// use serde_json::Value;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     lambda_runtime::run_with_init(|| async {
+///         let table = std::env::var("TABLE_NAME")?;
+///         Ok::<_, Error>(service_fn(move |event: LambdaEvent<Value>| {
+///             let table = table.clone();
+///             async move { Ok::<_, Error>(format!("{table}: {}", event.payload)) }
+///         }))
+///     })
+///     .await
+/// }
+/// ```
+pub async fn run_with_init<I, Fut, IE, A, F, R, B, S, D, E>(init: I) -> Result<(), Error>
+where
+    I: FnOnce() -> Fut,
+    Fut: Future<Output = Result<F, IE>>,
+    IE: for<'a> Into<Diagnostic<'a>> + fmt::Debug,
+    F: Service<LambdaEvent<A>, Response = R>,
+    F::Future: Future<Output = Result<R, F::Error>>,
+    F::Error: for<'a> Into<Diagnostic<'a>> + fmt::Debug,
+    A: for<'de> Deserialize<'de>,
+    R: IntoFunctionResponse<B, S>,
+    B: Serialize,
+    S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
+    D: Into<bytes::Bytes> + Send,
+    E: Into<Error> + Send + Debug,
+{
+    let runtime = Runtime::new_with_init(init).await?.layer(layers::TracingLayer::new());
     runtime.run().await
 }
@@ -0,0 +1,411 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    task,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::LambdaInvocation;
+use futures::ready;
+use pin_project::pin_project;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tokio::task::futures::TaskLocalFuture;
+use tower::{Layer, Service};
+
+tokio::task_local! {
+    static METRICS: Metrics;
+}
+
+/// Unit of a metric, as defined by [Amazon CloudWatch](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_MetricDatum.html).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[non_exhaustive]
+pub enum Unit {
+    /// Seconds.
+    Seconds,
+    /// Microseconds.
+    Microseconds,
+    /// Milliseconds.
+    Milliseconds,
+    /// Bytes.
+    Bytes,
+    /// Kilobytes.
+    Kilobytes,
+    /// Megabytes.
+    Megabytes,
+    /// Gigabytes.
+    Gigabytes,
+    /// Terabytes.
+    Terabytes,
+    /// Bits.
+    Bits,
+    /// Kilobits.
+    Kilobits,
+    /// Megabits.
+    Megabits,
+    /// Gigabits.
+    Gigabits,
+    /// Terabits.
+    Terabits,
+    /// Percent.
+    Percent,
+    /// Count.
+    Count,
+    /// Bytes per second.
+    #[serde(rename = "Bytes/Second")]
+    BytesPerSecond,
+    /// Kilobytes per second.
+    #[serde(rename = "Kilobytes/Second")]
+    KilobytesPerSecond,
+    /// Megabytes per second.
+    #[serde(rename = "Megabytes/Second")]
+    MegabytesPerSecond,
+    /// Gigabytes per second.
+    #[serde(rename = "Gigabytes/Second")]
+    GigabytesPerSecond,
+    /// Terabytes per second.
+    #[serde(rename = "Terabytes/Second")]
+    TerabytesPerSecond,
+    /// Bits per second.
+    #[serde(rename = "Bits/Second")]
+    BitsPerSecond,
+    /// Kilobits per second.
+    #[serde(rename = "Kilobits/Second")]
+    KilobitsPerSecond,
+    /// Megabits per second.
+    #[serde(rename = "Megabits/Second")]
+    MegabitsPerSecond,
+    /// Gigabits per second.
+    #[serde(rename = "Gigabits/Second")]
+    GigabitsPerSecond,
+    /// Terabits per second.
+    #[serde(rename = "Terabits/Second")]
+    TerabitsPerSecond,
+    /// Count per second.
+    #[serde(rename = "Count/Second")]
+    CountPerSecond,
+    /// No unit.
+    None,
+}
+
+#[derive(Debug)]
+struct MetricValue {
+    value: f64,
+    unit: Unit,
+}
+
+#[derive(Debug)]
+struct MetricsContext {
+    namespace: Arc<str>,
+    dimensions: BTreeMap<String, String>,
+    metrics: BTreeMap<String, MetricValue>,
+    properties: BTreeMap<String, Value>,
+}
+
+/// Metrics context of the invocation in progress.
+///
+/// The [MetricsLayer] opens a new context for every invocation, and writes it to stdout as
+/// an [Embedded Metric Format](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html)
+/// document when the invocation finishes. Use [Metrics::current] to record metrics from the handler.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{metrics::{Metrics, MetricsLayer, Unit}, Error, LambdaEvent, Runtime};
+/// use serde_json::Value;
+/// use tower::service_fn;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     Runtime::new(service_fn(handler)).layer(MetricsLayer::new("Orders")).run().await
+/// }
+///
+/// async fn handler(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     if let Some(metrics) = Metrics::current() {
+///         metrics.dimension("Channel", "web");
+///         metrics.counter("OrdersPlaced", 1.0);
+///         metrics.gauge("CartSize", 3.0, Unit::Count);
+///     }
+///     Ok(event.payload)
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    context: Arc<Mutex<MetricsContext>>,
+}
+
+impl Metrics {
+    fn new(namespace: Arc<str>) -> Self {
+        let context = MetricsContext {
+            namespace,
+            dimensions: BTreeMap::new(),
+            metrics: BTreeMap::new(),
+            properties: BTreeMap::new(),
+        };
+        Self {
+            context: Arc::new(Mutex::new(context)),
+        }
+    }
+
+    /// Return the metrics context of the invocation processed by the current task,
+    /// or `None` if the runtime doesn't use a [MetricsLayer].
+    pub fn current() -> Option<Metrics> {
+        METRICS.try_with(Clone::clone).ok()
+    }
+
+    /// Add `value` to a counter. The counter starts at zero in every invocation.
+    pub fn counter(&self, name: impl Into<String>, value: f64) {
+        self.lock()
+            .metrics
+            .entry(name.into())
+            .and_modify(|metric| metric.value += value)
+            .or_insert(MetricValue {
+                value,
+                unit: Unit::Count,
+            });
+    }
+
+    /// Set the value of a gauge. Only the last value recorded in an invocation is reported.
+    pub fn gauge(&self, name: impl Into<String>, value: f64, unit: Unit) {
+        self.lock().metrics.insert(name.into(), MetricValue { value, unit });
+    }
+
+    /// Add a dimension to all the metrics of the invocation.
+    pub fn dimension(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.lock().dimensions.insert(name.into(), value.into());
+    }
+
+    /// Add a property to the document of the invocation. Properties are not metrics, but
+    /// they can be searched with CloudWatch Logs Insights.
+    pub fn property(&self, name: impl Into<String>, value: impl Into<Value>) {
+        self.lock().properties.insert(name.into(), value.into());
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MetricsContext> {
+        self.context.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Build the EMF document with all the values recorded so far.
+    fn document(&self, timestamp: u64) -> Value {
+        let context = self.lock();
+
+        let dimension_set: Vec<&str> = context.dimensions.keys().map(String::as_str).collect();
+        let definitions: Vec<Value> = context
+            .metrics
+            .iter()
+            .map(|(name, metric)| json!({ "Name": name, "Unit": metric.unit }))
+            .collect();
+
+        let mut document = Map::new();
+        document.insert(
+            "_aws".to_string(),
+            json!({
+                "Timestamp": timestamp,
+                "CloudWatchMetrics": [{
+                    "Namespace": context.namespace.as_ref(),
+                    "Dimensions": [dimension_set],
+                    "Metrics": definitions,
+                }],
+            }),
+        );
+        for (name, value) in &context.properties {
+            document.insert(name.clone(), value.clone());
+        }
+        for (name, value) in &context.dimensions {
+            document.insert(name.clone(), Value::String(value.clone()));
+        }
+        for (name, metric) in &context.metrics {
+            document.insert(name.clone(), json!(metric.value));
+        }
+        Value::Object(document)
+    }
+
+    fn flush(&self) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        println!("{}", self.document(timestamp));
+    }
+}
+
+/// Tower layer that records [Embedded Metric Format](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html)
+/// metrics for every invocation.
+///
+/// Every invocation gets its own [Metrics] context, with a `function_name` dimension, `version`
+/// and `request_id` properties, and a `ColdStart` metric set to `1` for the first invocation of the
+/// execution environment. The context is written to stdout when the invocation finishes.
+pub struct MetricsLayer {
+    namespace: Arc<str>,
+}
+
+impl MetricsLayer {
+    /// Create a new [MetricsLayer] that publishes metrics in the given CloudWatch namespace.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into().into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            namespace: self.namespace.clone(),
+            coldstart: Arc::new(AtomicBool::new(true)),
+        }
+    }
+}
+
+/// Tower service created by [MetricsLayer].
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+    namespace: Arc<str>,
+    // Shared between clones, so only the first invocation of the process is a cold start
+    // when invocations are processed concurrently.
+    coldstart: Arc<AtomicBool>,
+}
+
+impl<S> Service<LambdaInvocation> for MetricsService<S>
+where
+    S: Service<LambdaInvocation, Response = ()>,
+{
+    type Error = S::Error;
+    type Response = ();
+    type Future = MetricsFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaInvocation) -> Self::Future {
+        let coldstart = self.coldstart.swap(false, Ordering::Relaxed);
+
+        let metrics = Metrics::new(self.namespace.clone());
+        metrics.dimension("function_name", req.context.env_config.function_name.as_str());
+        metrics.property("version", req.context.env_config.version.as_str());
+        metrics.property("request_id", req.context.request_id.as_str());
+        metrics.gauge("ColdStart", if coldstart { 1.0 } else { 0.0 }, Unit::Count);
+
+        let future = METRICS.sync_scope(metrics.clone(), || self.inner.call(req));
+        MetricsFuture {
+            future: METRICS.scope(metrics.clone(), future),
+            metrics: Some(metrics),
+        }
+    }
+}
+
+/// Future created by [MetricsService].
+#[pin_project]
+pub struct MetricsFuture<F> {
+    #[pin]
+    future: TaskLocalFuture<Metrics, F>,
+    metrics: Option<Metrics>,
+}
+
+impl<F> Future for MetricsFuture<F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+        let this = self.project();
+        let output = ready!(this.future.poll(cx));
+        if let Some(metrics) = this.metrics.take() {
+            metrics.flush();
+        }
+        task::Poll::Ready(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Context, Error};
+    use bytes::Bytes;
+
+    fn invocation(request_id: &str) -> LambdaInvocation {
+        let (parts, _) = http::Response::new(()).into_parts();
+        let config = Config::builder()
+            .with_function_name("test-function")
+            .with_version("$LATEST")
+            .build();
+        let context = Context {
+            request_id: request_id.to_string(),
+            env_config: Arc::new(config),
+            ..Default::default()
+        };
+        LambdaInvocation::new(parts, Bytes::new(), context)
+    }
+
+    #[tokio::test]
+    async fn records_metrics_for_each_invocation() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let captured = recorded.clone();
+        let inner = tower::service_fn(move |_: LambdaInvocation| {
+            let captured = captured.clone();
+            async move {
+                let metrics = Metrics::current().expect("missing metrics context");
+                metrics.counter("Orders", 1.0);
+                metrics.counter("Orders", 2.0);
+                metrics.dimension("Channel", "web");
+                metrics.property("customer", "abc");
+                captured.lock().unwrap().push(metrics);
+                Ok::<(), Error>(())
+            }
+        });
+
+        let mut service = MetricsLayer::new("Shop").layer(inner);
+        service.call(invocation("first")).await.unwrap();
+        service.call(invocation("second")).await.unwrap();
+
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(
+            json!({
+                "_aws": {
+                    "Timestamp": 1000,
+                    "CloudWatchMetrics": [{
+                        "Namespace": "Shop",
+                        "Dimensions": [["Channel", "function_name"]],
+                        "Metrics": [
+                            { "Name": "ColdStart", "Unit": "Count" },
+                            { "Name": "Orders", "Unit": "Count" },
+                        ],
+                    }],
+                },
+                "Channel": "web",
+                "function_name": "test-function",
+                "version": "$LATEST",
+                "request_id": "first",
+                "customer": "abc",
+                "ColdStart": 1.0,
+                "Orders": 3.0,
+            }),
+            recorded[0].document(1000)
+        );
+
+        let second = recorded[1].document(1000);
+        assert_eq!(json!("second"), second["request_id"]);
+        assert_eq!(json!(0.0), second["ColdStart"]);
+    }
+
+    #[test]
+    fn no_metrics_outside_invocations() {
+        assert!(Metrics::current().is_none());
+    }
+
+    #[test]
+    fn units_use_cloudwatch_names() {
+        assert_eq!(json!("Bytes/Second"), json!(Unit::BytesPerSecond));
+        assert_eq!(json!("None"), json!(Unit::None));
+    }
+}
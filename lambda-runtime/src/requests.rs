@@ -1,10 +1,25 @@
-use crate::{types::ToStreamErrorTrailer, Diagnostic, Error, FunctionResponse, IntoFunctionResponse};
+use crate::{
+    codec::{Encode, JsonCodec},
+    diagnostic::XRayErrorCause,
+    types::{ResponseSizePolicy, ToStreamErrorTrailer},
+    Diagnostic, Error, FunctionResponse, IntoFunctionResponse,
+};
 use bytes::Bytes;
-use http::{header::CONTENT_TYPE, Method, Request, Uri};
-use lambda_runtime_api_client::{body::Body, build_request};
-use serde::Serialize;
-use std::{fmt::Debug, marker::PhantomData, str::FromStr};
+use http::{header::CONTENT_TYPE, HeaderValue, Method, Request, Uri};
+use lambda_runtime_api_client::{
+    api::runtime::{
+        self as api, FUNCTION_ERROR_BODY_HEADER, FUNCTION_ERROR_TYPE_HEADER, RESPONSE_MODE_HEADER,
+        XRAY_ERROR_CAUSE_HEADER,
+    },
+    body::Body,
+    build_request,
+};
+use std::{borrow::Cow, fmt::Debug, marker::PhantomData, str::FromStr};
 use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, error, trace};
+
+/// Maximum size of a buffered response accepted by the Lambda Runtime API, 6 MB.
+pub(crate) const MAX_BUFFERED_RESPONSE_SIZE: usize = 6 * 1024 * 1024;
 
 pub(crate) trait IntoRequest {
     fn into_req(self) -> Result<Request<Body>, Error>;
@@ -16,66 +31,81 @@ pub(crate) struct NextEventRequest;
 
 impl IntoRequest for NextEventRequest {
     fn into_req(self) -> Result<Request<Body>, Error> {
-        let req = build_request()
-            .method(Method::GET)
-            .uri(Uri::from_static("/2018-06-01/runtime/invocation/next"))
-            .body(Default::default())?;
-        Ok(req)
+        Ok(api::next_invocation()?)
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-pub struct NextEventResponse<'a> {
-    // lambda-runtime-aws-request-id
-    pub request_id: &'a str,
-    // lambda-runtime-deadline-ms
-    pub deadline: u64,
-    // lambda-runtime-invoked-function-arn
-    pub arn: &'a str,
-    // lambda-runtime-trace-id
-    pub trace_id: &'a str,
-    // the actual body,
-    pub body: Vec<u8>,
-}
-
 // /runtime/invocation/{AwsRequestId}/response
-pub(crate) struct EventCompletionRequest<'a, R, B, S, D, E>
+pub(crate) struct EventCompletionRequest<'a, R, B, S, D, E, C = JsonCodec>
 where
     R: IntoFunctionResponse<B, S>,
-    B: Serialize,
     S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
     D: Into<Bytes> + Send,
     E: Into<Error> + Send + Debug,
 {
     pub(crate) request_id: &'a str,
+    pub(crate) xray_trace_id: Option<&'a str>,
     pub(crate) body: R,
+    pub(crate) codec: C,
+    pub(crate) response_size_policy: ResponseSizePolicy,
     pub(crate) _unused_b: PhantomData<B>,
     pub(crate) _unused_s: PhantomData<S>,
 }
 
+#[cfg(test)]
 impl<'a, R, B, D, E, S> EventCompletionRequest<'a, R, B, S, D, E>
 where
     R: IntoFunctionResponse<B, S>,
-    B: Serialize,
+    B: serde::Serialize,
     S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
     D: Into<Bytes> + Send,
     E: Into<Error> + Send + Debug,
 {
-    /// Initialize a new EventCompletionRequest
+    /// Initialize a new EventCompletionRequest that encodes buffered responses as JSON
     pub(crate) fn new(request_id: &'a str, body: R) -> EventCompletionRequest<'a, R, B, S, D, E> {
+        EventCompletionRequest::with_codec(request_id, body, JsonCodec)
+    }
+}
+
+impl<'a, R, B, D, E, S, C> EventCompletionRequest<'a, R, B, S, D, E, C>
+where
+    R: IntoFunctionResponse<B, S>,
+    S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
+    D: Into<Bytes> + Send,
+    E: Into<Error> + Send + Debug,
+{
+    /// Initialize a new EventCompletionRequest that encodes buffered responses with the given codec
+    pub(crate) fn with_codec(request_id: &'a str, body: R, codec: C) -> EventCompletionRequest<'a, R, B, S, D, E, C> {
         EventCompletionRequest {
             request_id,
+            xray_trace_id: None,
             body,
+            codec,
+            response_size_policy: ResponseSizePolicy::default(),
             _unused_b: PhantomData::<B>,
             _unused_s: PhantomData::<S>,
         }
     }
+
+    /// Set the X-Ray trace id of the invocation, used to report responses that exceed
+    /// the maximum payload size
+    pub(crate) fn with_xray_trace_id(self, xray_trace_id: Option<&'a str>) -> Self {
+        EventCompletionRequest { xray_trace_id, ..self }
+    }
+
+    /// Set what to do with buffered responses that exceed the maximum payload size
+    pub(crate) fn with_response_size_policy(self, response_size_policy: ResponseSizePolicy) -> Self {
+        EventCompletionRequest {
+            response_size_policy,
+            ..self
+        }
+    }
 }
 
-impl<'a, R, B, S, D, E> IntoRequest for EventCompletionRequest<'a, R, B, S, D, E>
+impl<'a, R, B, S, D, E, C> IntoRequest for EventCompletionRequest<'a, R, B, S, D, E, C>
 where
     R: IntoFunctionResponse<B, S>,
-    B: Serialize,
+    C: Encode<B>,
     S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
     D: Into<Bytes> + Send,
     E: Into<Error> + Send + Debug,
@@ -83,28 +113,32 @@ where
     fn into_req(self) -> Result<Request<Body>, Error> {
         match self.body.into_response() {
             FunctionResponse::BufferedResponse(body) => {
-                let uri = format!("/2018-06-01/runtime/invocation/{}/response", self.request_id);
-                let uri = Uri::from_str(&uri)?;
-
-                let body = serde_json::to_vec(&body)?;
-                let body = Body::from(body);
+                let body = self.codec.encode(body)?;
+                if body.len() > MAX_BUFFERED_RESPONSE_SIZE {
+                    let uri = Uri::from_str(&api::invocation_response_path(self.request_id))?;
+                    return response_too_large_request(
+                        self.request_id,
+                        self.xray_trace_id,
+                        uri,
+                        body,
+                        self.response_size_policy,
+                    );
+                }
 
-                let req = build_request().method(Method::POST).uri(uri).body(body)?;
-                Ok(req)
+                Ok(api::invocation_response(self.request_id, body)?)
             }
             FunctionResponse::StreamingResponse(mut response) => {
-                let uri = format!("/2018-06-01/runtime/invocation/{}/response", self.request_id);
-                let uri = Uri::from_str(&uri)?;
+                let uri = Uri::from_str(&api::invocation_response_path(self.request_id))?;
 
                 let mut builder = build_request().method(Method::POST).uri(uri);
                 let req_headers = builder.headers_mut().unwrap();
 
                 req_headers.insert("Transfer-Encoding", "chunked".parse()?);
-                req_headers.insert("Lambda-Runtime-Function-Response-Mode", "streaming".parse()?);
+                req_headers.insert(RESPONSE_MODE_HEADER, "streaming".parse()?);
                 // Report midstream errors using error trailers.
                 // See the details in Lambda Developer Doc: https://docs.aws.amazon.com/lambda/latest/dg/runtimes-custom.html#runtimes-custom-response-streaming
-                req_headers.append("Trailer", "Lambda-Runtime-Function-Error-Type".parse()?);
-                req_headers.append("Trailer", "Lambda-Runtime-Function-Error-Body".parse()?);
+                req_headers.append("Trailer", FUNCTION_ERROR_TYPE_HEADER.parse()?);
+                req_headers.append("Trailer", FUNCTION_ERROR_BODY_HEADER.parse()?);
                 req_headers.insert(
                     "Content-Type",
                     "application/vnd.awslambda.http-integration-response".parse()?,
@@ -118,7 +152,7 @@ where
 
                 let metadata_prelude = serde_json::to_string(&response.metadata_prelude)?;
 
-                tracing::trace!(?metadata_prelude);
+                trace!(?metadata_prelude);
 
                 let (mut tx, rx) = Body::channel();
 
@@ -129,7 +163,7 @@ where
                     while let Some(chunk) = response.stream.next().await {
                         let chunk = match chunk {
                             Ok(chunk) => chunk.into(),
-                            Err(err) => err.into().to_tailer().into(),
+                            Err(err) => stream_error_trailer(err.into()).into(),
                         };
                         tx.send_data(chunk).await.unwrap();
                     }
@@ -155,9 +189,70 @@ fn test_event_completion_request() {
     });
 }
 
+/// Build the error trailer of a streaming response that failed.
+///
+/// Errors that are a boxed [Diagnostic] are reported as is, other errors are reported
+/// with the `Runtime.StreamError` error type.
+fn stream_error_trailer(err: Error) -> String {
+    let diagnostic = match err.downcast::<Diagnostic<'static>>() {
+        Ok(diagnostic) => *diagnostic,
+        Err(err) => Diagnostic {
+            error_type: Cow::Borrowed("Runtime.StreamError"),
+            ..Diagnostic::from(err)
+        },
+    };
+    error!(error = ?diagnostic, "streaming response failed");
+    diagnostic.to_tailer()
+}
+
+/// Build the request for a buffered response that exceeds the maximum payload size,
+/// according to the response size policy of the runtime.
+fn response_too_large_request(
+    request_id: &str,
+    xray_trace_id: Option<&str>,
+    uri: Uri,
+    body: Bytes,
+    policy: ResponseSizePolicy,
+) -> Result<Request<Body>, Error> {
+    match policy {
+        ResponseSizePolicy::Error => {
+            error!(size = body.len(), "buffered response exceeds the maximum payload size");
+            let diagnostic = Diagnostic {
+                error_type: Cow::Borrowed("Function.ResponseSizeTooLarge"),
+                error_message: Cow::Owned(format!(
+                    "Response payload size ({} bytes) exceeded maximum allowed payload size ({MAX_BUFFERED_RESPONSE_SIZE} bytes).",
+                    body.len()
+                )),
+                ..Default::default()
+            };
+            EventErrorRequest::new(request_id, diagnostic)
+                .with_xray_trace_id(xray_trace_id)
+                .into_req()
+        }
+        ResponseSizePolicy::Stream => {
+            debug!(size = body.len(), "sending large buffered response in streaming mode");
+            let req = build_request()
+                .method(Method::POST)
+                .uri(uri)
+                .header("Transfer-Encoding", "chunked")
+                .header(RESPONSE_MODE_HEADER, "streaming")
+                .body(Body::from(body))?;
+            Ok(req)
+        }
+    }
+}
+
+/// Marks the requests that report the error of an invocation, as opposed to its response.
+///
+/// [EventErrorRequest] stores it in the request extensions, so the layers that send the
+/// request know its kind without parsing the URI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct InvocationErrorReport;
+
 // /runtime/invocation/{AwsRequestId}/error
 pub(crate) struct EventErrorRequest<'a> {
     pub(crate) request_id: &'a str,
+    pub(crate) xray_trace_id: Option<&'a str>,
     pub(crate) diagnostic: Diagnostic<'a>,
 }
 
@@ -165,47 +260,90 @@ impl<'a> EventErrorRequest<'a> {
     pub(crate) fn new(request_id: &'a str, diagnostic: impl Into<Diagnostic<'a>>) -> EventErrorRequest<'a> {
         EventErrorRequest {
             request_id,
+            xray_trace_id: None,
             diagnostic: diagnostic.into(),
         }
     }
+
+    /// Set the X-Ray trace id of the invocation. When it's present, the error
+    /// cause is also reported to X-Ray.
+    pub(crate) fn with_xray_trace_id(self, xray_trace_id: Option<&'a str>) -> EventErrorRequest<'a> {
+        EventErrorRequest { xray_trace_id, ..self }
+    }
 }
 
 impl<'a> IntoRequest for EventErrorRequest<'a> {
     fn into_req(self) -> Result<Request<Body>, Error> {
-        let uri = format!("/2018-06-01/runtime/invocation/{}/error", self.request_id);
-        let uri = Uri::from_str(&uri)?;
-        let body = serde_json::to_vec(&self.diagnostic)?;
-        let body = Body::from(body);
-
-        let req = build_request()
-            .method(Method::POST)
-            .uri(uri)
-            .header("lambda-runtime-function-error-type", "unhandled")
-            .body(body)?;
+        let mut req = api::invocation_error(self.request_id, "unhandled", &self.diagnostic)?;
+        req.extensions_mut().insert(InvocationErrorReport);
+
+        if self.xray_trace_id.is_some() {
+            let xray_cause = match &self.diagnostic.xray_cause {
+                Some(xray_cause) => xray_cause.to_header_value(),
+                None => XRayErrorCause::from(&self.diagnostic).to_header_value(),
+            };
+            if let Some(xray_cause) = xray_cause {
+                req.headers_mut()
+                    .insert(XRAY_ERROR_CAUSE_HEADER, HeaderValue::try_from(xray_cause)?);
+            }
+        }
+
         Ok(req)
     }
 }
 
 // /runtime/init/error
-struct InitErrorRequest;
+pub(crate) struct InitErrorRequest<'a> {
+    pub(crate) diagnostic: Diagnostic<'a>,
+}
+
+impl<'a> InitErrorRequest<'a> {
+    pub(crate) fn new(diagnostic: impl Into<Diagnostic<'a>>) -> InitErrorRequest<'a> {
+        InitErrorRequest {
+            diagnostic: diagnostic.into(),
+        }
+    }
+}
 
-impl IntoRequest for InitErrorRequest {
+impl<'a> IntoRequest for InitErrorRequest<'a> {
     fn into_req(self) -> Result<Request<Body>, Error> {
-        let uri = "/2018-06-01/runtime/init/error".to_string();
-        let uri = Uri::from_str(&uri)?;
-
-        let req = build_request()
-            .method(Method::POST)
-            .uri(uri)
-            .header("lambda-runtime-function-error-type", "unhandled")
-            .body(Body::empty())?;
-        Ok(req)
+        Ok(api::init_error("unhandled", &self.diagnostic)?)
+    }
+}
+
+// /runtime/restore/next
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct RestoreNextRequest;
+
+impl IntoRequest for RestoreNextRequest {
+    fn into_req(self) -> Result<Request<Body>, Error> {
+        Ok(api::restore_next()?)
+    }
+}
+
+// /runtime/restore/error
+pub(crate) struct RestoreErrorRequest<'a> {
+    pub(crate) diagnostic: Diagnostic<'a>,
+}
+
+impl<'a> RestoreErrorRequest<'a> {
+    pub(crate) fn new(diagnostic: impl Into<Diagnostic<'a>>) -> RestoreErrorRequest<'a> {
+        RestoreErrorRequest {
+            diagnostic: diagnostic.into(),
+        }
+    }
+}
+
+impl<'a> IntoRequest for RestoreErrorRequest<'a> {
+    fn into_req(self) -> Result<Request<Body>, Error> {
+        Ok(api::restore_error("unhandled", &self.diagnostic)?)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::codec::RawCodec;
 
     #[test]
     fn test_next_event_request() {
@@ -223,24 +361,55 @@ mod tests {
     fn test_event_error_request() {
         let req = EventErrorRequest {
             request_id: "id",
+            xray_trace_id: None,
             diagnostic: Diagnostic {
                 error_type: std::borrow::Cow::Borrowed("InvalidEventDataError"),
                 error_message: std::borrow::Cow::Borrowed("Error parsing event data"),
+                ..Default::default()
             },
         };
         let req = req.into_req().unwrap();
         let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/error");
         assert_eq!(req.method(), Method::POST);
         assert_eq!(req.uri(), &expected);
+        assert!(req.extensions().get::<InvocationErrorReport>().is_some());
         assert!(match req.headers().get("User-Agent") {
             Some(header) => header.to_str().unwrap().starts_with("aws-lambda-rust/"),
             None => false,
         });
     }
 
+    #[test]
+    fn test_event_error_request_with_xray_cause() {
+        let diagnostic = Diagnostic {
+            error_type: std::borrow::Cow::Borrowed("InvalidEventDataError"),
+            error_message: std::borrow::Cow::Borrowed("Error parsing event data"),
+            ..Default::default()
+        };
+
+        let req = EventErrorRequest::new("id", diagnostic.clone());
+        let req = req.into_req().unwrap();
+        assert!(req.headers().get(XRAY_ERROR_CAUSE_HEADER).is_none());
+
+        let req = EventErrorRequest::new("id", diagnostic)
+            .with_xray_trace_id(Some("Root=1-5759e988-bd862e3fe1be46a994272793"));
+        let req = req.into_req().unwrap();
+        let header = req
+            .headers()
+            .get(XRAY_ERROR_CAUSE_HEADER)
+            .expect("missing X-Ray error cause header");
+        let cause: XRayErrorCause = serde_json::from_slice(header.as_bytes()).unwrap();
+        assert_eq!(cause.exceptions[0].error_type, "InvalidEventDataError");
+        assert_eq!(cause.exceptions[0].message, "Error parsing event data");
+    }
+
     #[test]
     fn test_init_error_request() {
-        let req = InitErrorRequest;
+        let req = InitErrorRequest::new(Diagnostic {
+            error_type: std::borrow::Cow::Borrowed("InitError"),
+            error_message: std::borrow::Cow::Borrowed("Unable to load secrets"),
+            ..Default::default()
+        });
         let req = req.into_req().unwrap();
         let expected = Uri::from_static("/2018-06-01/runtime/init/error");
         assert_eq!(req.method(), Method::POST);
@@ -250,4 +419,107 @@ mod tests {
             None => false,
         });
     }
+
+    #[test]
+    fn test_restore_next_request() {
+        let req = RestoreNextRequest.into_req().unwrap();
+        assert_eq!(req.method(), Method::GET);
+        assert_eq!(req.uri(), &Uri::from_static("/2018-06-01/runtime/restore/next"));
+    }
+
+    #[test]
+    fn test_restore_error_request() {
+        let req = RestoreErrorRequest::new(Diagnostic {
+            error_type: std::borrow::Cow::Borrowed("RestoreError"),
+            error_message: std::borrow::Cow::Borrowed("Unable to reconnect"),
+            ..Default::default()
+        });
+        let req = req.into_req().unwrap();
+        assert_eq!(req.method(), Method::POST);
+        assert_eq!(req.uri(), &Uri::from_static("/2018-06-01/runtime/restore/error"));
+        assert_eq!(
+            req.headers().get("lambda-runtime-function-error-type").unwrap(),
+            "unhandled"
+        );
+    }
+
+    #[test]
+    fn test_event_completion_request_too_large() {
+        let body = "a".repeat(MAX_BUFFERED_RESPONSE_SIZE);
+        let req = EventCompletionRequest::new("id", body);
+        let req = req.into_req().unwrap();
+        let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/error");
+        assert_eq!(req.method(), Method::POST);
+        assert_eq!(req.uri(), &expected);
+        assert!(req.extensions().get::<InvocationErrorReport>().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_event_completion_request_too_large_error_body() {
+        let body = "a".repeat(MAX_BUFFERED_RESPONSE_SIZE);
+        let req = EventCompletionRequest::new("id", body).into_req().unwrap();
+        let body = http_body_util::BodyExt::collect(req.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        let diagnostic: Diagnostic<'_> = serde_json::from_slice(&body).unwrap();
+        assert_eq!("Function.ResponseSizeTooLarge", diagnostic.error_type);
+    }
+
+    #[test]
+    fn test_event_completion_request_too_large_streamed() {
+        let body = "a".repeat(MAX_BUFFERED_RESPONSE_SIZE);
+        let req = EventCompletionRequest::new("id", body).with_response_size_policy(ResponseSizePolicy::Stream);
+        let req = req.into_req().unwrap();
+        let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/response");
+        assert_eq!(req.uri(), &expected);
+        assert_eq!(
+            req.headers().get("Lambda-Runtime-Function-Response-Mode").unwrap(),
+            "streaming"
+        );
+    }
+
+    #[test]
+    fn test_event_completion_request_at_max_size() {
+        let body = Bytes::from(vec![b'a'; MAX_BUFFERED_RESPONSE_SIZE]);
+        let req = EventCompletionRequest::with_codec("id", body, RawCodec);
+        let req = req.into_req().unwrap();
+        let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/response");
+        assert_eq!(req.uri(), &expected);
+        assert!(req.headers().get("Lambda-Runtime-Function-Response-Mode").is_none());
+    }
+
+    #[test]
+    fn test_event_completion_request_over_max_size() {
+        let body = Bytes::from(vec![b'a'; MAX_BUFFERED_RESPONSE_SIZE + 1]);
+        let req = EventCompletionRequest::with_codec("id", body, RawCodec);
+        let req = req.into_req().unwrap();
+        let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/error");
+        assert_eq!(req.uri(), &expected);
+        assert!(req.extensions().get::<InvocationErrorReport>().is_some());
+    }
+
+    #[test]
+    fn test_event_completion_request_too_large_with_xray_cause() {
+        let body = Bytes::from(vec![b'a'; MAX_BUFFERED_RESPONSE_SIZE + 1]);
+        let req = EventCompletionRequest::with_codec("id", body, RawCodec)
+            .with_xray_trace_id(Some("Root=1-5759e988-bd862e3fe1be46a994272793"));
+        let req = req.into_req().unwrap();
+        let header = req
+            .headers()
+            .get(XRAY_ERROR_CAUSE_HEADER)
+            .expect("missing X-Ray error cause header");
+        let cause: XRayErrorCause = serde_json::from_slice(header.as_bytes()).unwrap();
+        assert_eq!(cause.exceptions[0].error_type, "Function.ResponseSizeTooLarge");
+    }
+
+    #[test]
+    fn test_stream_error_trailer() {
+        let trailer = stream_error_trailer("connection reset".into());
+        assert!(trailer.starts_with("Lambda-Runtime-Function-Error-Type: Runtime.StreamError\r\n"));
+
+        let diagnostic = Diagnostic::new("UpstreamTimeout", "model stopped responding");
+        let trailer = stream_error_trailer(diagnostic.into());
+        assert!(trailer.starts_with("Lambda-Runtime-Function-Error-Type: UpstreamTimeout\r\n"));
+    }
 }
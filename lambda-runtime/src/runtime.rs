@@ -1,20 +1,41 @@
 use crate::{
+    codec::{Decode, Encode, JsonCodec},
     layers::{CatchPanicService, RuntimeApiClientService, RuntimeApiResponseService},
-    requests::{IntoRequest, NextEventRequest},
+    requests::{
+        EventErrorRequest, InitErrorRequest, IntoRequest, NextEventRequest, RestoreErrorRequest, RestoreNextRequest,
+    },
     types::{invoke_request_id, IntoFunctionResponse, LambdaEvent},
-    Config, Context, Diagnostic,
+    Config, Context, Diagnostic, Handler, InitializationType, ResponseSizePolicy, RuntimeConfigError, StateService,
+};
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt,
 };
 use http_body_util::BodyExt;
-use lambda_runtime_api_client::{BoxError, Client as ApiClient};
+use lambda_runtime_api_client::{
+    api::runtime::{NextInvocation, TRACE_ID_HEADER},
+    BoxError, CallLatency, Client as ApiClient, RetryPolicy,
+};
 use serde::{Deserialize, Serialize};
-use std::{env, fmt::Debug, future::Future, sync::Arc};
+use std::{
+    borrow::Cow,
+    env,
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
 use tokio_stream::{Stream, StreamExt};
 use tower::{Layer, Service, ServiceExt};
-use tracing::trace;
+use tracing::{error, trace};
+
+const MAX_CONCURRENCY_ENV_VAR: &str = "AWS_LAMBDA_MAX_CONCURRENCY";
 
 /* ----------------------------------------- INVOCATION ---------------------------------------- */
 
 /// A simple container that provides information about a single invocation of a Lambda function.
+#[non_exhaustive]
 pub struct LambdaInvocation {
     /// The header of the request sent to invoke the Lambda function.
     pub parts: http::response::Parts,
@@ -22,6 +43,120 @@ pub struct LambdaInvocation {
     pub body: bytes::Bytes,
     /// The context of the Lambda invocation.
     pub context: Context,
+    /// The time spent talking to the Runtime API for this invocation.
+    pub timings: InvocationTimings,
+}
+
+impl LambdaInvocation {
+    /// Create a new invocation, with empty timings.
+    ///
+    /// The runtime creates the invocations it receives from the Runtime API. Use this
+    /// constructor to test layers without a Runtime API.
+    pub fn new(parts: http::response::Parts, body: bytes::Bytes, context: Context) -> Self {
+        Self {
+            parts,
+            body,
+            context,
+            timings: InvocationTimings::default(),
+        }
+    }
+}
+
+/// Runtime API client of the runtime that received an invocation.
+///
+/// The runtime stores it in the extensions of [LambdaInvocation::parts], so layers that
+/// report errors, like [DeadlineLayer](crate::layers::DeadlineLayer), send them with the
+/// same endpoint, transport and retry policy as the runtime.
+#[derive(Clone)]
+pub(crate) struct InvocationClient(pub(crate) Arc<ApiClient>);
+
+/// Timings of the calls to the Runtime API made by the runtime for a single invocation.
+///
+/// The timings are shared between all the clones of this value, so layers can keep a clone
+/// of [LambdaInvocation::timings] and read the timings of the response upload once the inner
+/// service has completed. Every timing is also emitted as a `tracing` field, in milliseconds,
+/// when the invocation completes.
+#[derive(Debug, Clone, Default)]
+pub struct InvocationTimings {
+    inner: Arc<Mutex<Timings>>,
+}
+
+#[derive(Debug, Default)]
+struct Timings {
+    next_event: Duration,
+    payload_download: Duration,
+    handler: Option<Duration>,
+    response_upload: Option<Duration>,
+    error_report: Option<Duration>,
+}
+
+impl InvocationTimings {
+    /// Time between requesting the next event and receiving the headers of the invocation,
+    /// including the time the runtime waited for an event to arrive.
+    pub fn next_event(&self) -> Duration {
+        self.lock().next_event
+    }
+
+    /// Time spent downloading the payload of the invocation.
+    pub fn payload_download(&self) -> Duration {
+        self.lock().payload_download
+    }
+
+    /// Time spent in the handler, including the time spent encoding its response.
+    ///
+    /// This value is `None` until the handler has completed. Once it's set, the runtime is
+    /// sending the result of the handler to the Runtime API.
+    pub fn handler(&self) -> Option<Duration> {
+        self.lock().handler
+    }
+
+    /// Time spent sending the response of the handler to the Runtime API, including
+    /// the time spent streaming the body of streaming responses.
+    ///
+    /// This value is `None` until the response has been accepted, or if the handler failed.
+    pub fn response_upload(&self) -> Option<Duration> {
+        self.lock().response_upload
+    }
+
+    /// Time spent reporting the error of the handler to the Runtime API.
+    ///
+    /// This value is `None` unless the handler failed and the error has been reported.
+    pub fn error_report(&self) -> Option<Duration> {
+        self.lock().error_report
+    }
+
+    /// Time the runtime spent talking to the Runtime API on behalf of this invocation,
+    /// excluding the time spent waiting for the event to arrive.
+    pub fn overhead(&self) -> Duration {
+        let timings = self.lock();
+        timings.payload_download
+            + timings.response_upload.unwrap_or_default()
+            + timings.error_report.unwrap_or_default()
+    }
+
+    pub(crate) fn set_next_event(&self, latency: Duration) {
+        self.lock().next_event = latency;
+    }
+
+    pub(crate) fn set_payload_download(&self, latency: Duration) {
+        self.lock().payload_download = latency;
+    }
+
+    pub(crate) fn set_handler(&self, duration: Duration) {
+        self.lock().handler = Some(duration);
+    }
+
+    pub(crate) fn set_response_upload(&self, latency: Duration) {
+        self.lock().response_upload = Some(latency);
+    }
+
+    pub(crate) fn set_error_report(&self, latency: Duration) {
+        self.lock().error_report = Some(latency);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Timings> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 /* ------------------------------------------ RUNTIME ------------------------------------------ */
@@ -53,6 +188,8 @@ pub struct Runtime<S> {
     service: S,
     config: Arc<Config>,
     client: Arc<ApiClient>,
+    shutdown: BoxFuture<'static, ()>,
+    snapstart: SnapStartHooks,
 }
 
 impl<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
@@ -89,14 +226,242 @@ where
     /// Note that manually creating a [Runtime] does not add tracing to the executed handler
     /// as is done by [super::run]. If you want to add the default tracing functionality, call
     /// [Runtime::layer] with a [super::layers::TracingLayer].
+    ///
+    /// # Panics
+    /// This function panics if the runtime cannot be configured from the environment variables
+    /// set by Lambda. Use [Runtime::try_new] to handle those errors.
     pub fn new(handler: F) -> Self {
-        trace!("Loading config from env");
-        let config = Arc::new(Config::from_env());
-        let client = Arc::new(ApiClient::builder().build().expect("Unable to create a runtime client"));
+        Self::new_with_codec(handler, JsonCodec)
+    }
+
+    /// Create a new runtime that executes the provided handler for incoming requests,
+    /// returning an error if the runtime cannot be configured from the environment.
+    pub fn try_new(handler: F) -> Result<Self, RuntimeConfigError> {
+        Self::try_new_with_codec(handler, JsonCodec)
+    }
+
+    /// Create a new runtime with the given configuration and Runtime API client,
+    /// instead of reading them from the environment.
+    ///
+    /// Use this constructor to send the requests to the Runtime API with a custom transport,
+    /// like an in-memory mock in tests or middleware that logs every request.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use lambda_runtime::{Config, Error, LambdaEvent, Runtime, RuntimeApiClient};
+    /// use serde_json::Value;
+    /// use tower::service_fn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Error> {
+    ///     let client = RuntimeApiClient::builder()
+    ///         .with_endpoint("http://localhost:9001".parse()?)
+    ///         .build()?;
+    ///     let config = Config::builder().with_function_name("local-function").build();
+    ///     Runtime::with_client(service_fn(echo), config, client).run().await
+    /// }
+    ///
+    /// async fn echo(event: LambdaEvent<Value>) -> Result<Value, Error> {
+    ///     Ok(event.payload)
+    /// }
+    /// ```
+    pub fn with_client(handler: F, config: Config, client: ApiClient) -> Self {
+        Self::with_codec_and_client(handler, JsonCodec, Arc::new(config), Arc::new(client))
+    }
+
+    /// Create a new runtime whose handler is built by the provided asynchronous init function.
+    ///
+    /// Use this constructor when the handler depends on fallible setup work, like loading
+    /// secrets or building SDK clients. If the init function fails, its error is reported to the
+    /// [Runtime API init error endpoint](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html#runtimes-api-initerror)
+    /// before it's returned, so the cause of the failure is visible in the Lambda console.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use lambda_runtime::{Error, LambdaEvent, Runtime};
+    /// use serde_json::Value;
+    /// use tower::service_fn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Error> {
+    ///     let runtime = Runtime::new_with_init(|| async {
+    ///         let prefix = std::env::var("GREETING_PREFIX")?;
+    ///         Ok::<_, Error>(service_fn(move |event: LambdaEvent<Value>| {
+    ///             let prefix = prefix.clone();
+    ///             async move { Ok::<_, Error>(format!("{prefix} {}", event.payload)) }
+    ///         }))
+    ///     })
+    ///     .await?;
+    ///     runtime.run().await
+    /// }
+    /// ```
+    pub async fn new_with_init<Init, InitFuture, InitError>(init: Init) -> Result<Self, BoxError>
+    where
+        Init: FnOnce() -> InitFuture,
+        InitFuture: Future<Output = Result<F, InitError>>,
+        InitError: Into<Diagnostic<'a>> + Debug,
+    {
+        let (config, client) = config_and_client_from_env()?;
+        let handler = run_init(&client, init).await?;
+        Ok(Self::with_codec_and_client(handler, JsonCodec, config, client))
+    }
+}
+
+impl<'a, H, State, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
+    Runtime<
+        RuntimeApiClientService<
+            RuntimeApiResponseService<
+                CatchPanicService<'a, StateService<H, State>>,
+                H::Event,
+                Response,
+                BufferedResponse,
+                StreamingResponse,
+                StreamItem,
+                StreamError,
+            >,
+        >,
+    >
+where
+    H: Handler<State, Response = Response> + Send + Sync + 'static,
+    H::Event: for<'de> Deserialize<'de> + Send + 'static,
+    H::Error: Into<Diagnostic<'a>> + Debug,
+    State: Send + Sync + 'static,
+    Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
+    BufferedResponse: Serialize,
+    StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
+    StreamItem: Into<bytes::Bytes> + Send,
+    StreamError: Into<BoxError> + Send + Debug,
+{
+    /// Create a new runtime that executes the provided [Handler] for incoming requests,
+    /// lending it the shared `state` on every invocation.
+    ///
+    /// The handler is wrapped in a [StateService], so panics are caught and responses are
+    /// sent to the Lambda Runtime API the same way as for [Runtime::new].
+    ///
+    /// # Panics
+    /// This function panics if the runtime cannot be configured from the environment variables
+    /// set by Lambda. Use [Runtime::try_with_state] to handle those errors.
+    pub fn with_state(state: State, handler: H) -> Self {
+        Self::new(StateService::new(handler, state))
+    }
+
+    /// Create a new runtime that executes the provided [Handler] for incoming requests,
+    /// lending it the shared `state` on every invocation, and returning an error if the
+    /// runtime cannot be configured from the environment.
+    pub fn try_with_state(state: State, handler: H) -> Result<Self, RuntimeConfigError> {
+        Self::try_new(StateService::new(handler, state))
+    }
+}
+
+impl<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, Codec>
+    Runtime<
+        RuntimeApiClientService<
+            RuntimeApiResponseService<
+                CatchPanicService<'a, F>,
+                EventPayload,
+                Response,
+                BufferedResponse,
+                StreamingResponse,
+                StreamItem,
+                StreamError,
+                Codec,
+            >,
+        >,
+    >
+where
+    F: Service<LambdaEvent<EventPayload>, Response = Response>,
+    F::Future: Future<Output = Result<Response, F::Error>>,
+    F::Error: Into<Diagnostic<'a>> + Debug,
+    Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
+    Codec: Decode<EventPayload> + Encode<BufferedResponse> + Clone,
+    StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
+    StreamItem: Into<bytes::Bytes> + Send,
+    StreamError: Into<BoxError> + Send + Debug,
+{
+    /// Create a new runtime that decodes event payloads and encodes buffered responses
+    /// with the provided codec, instead of JSON.
+    ///
+    /// Use [crate::codec::RawCodec] to receive payloads as raw [bytes::Bytes], or implement
+    /// [Decode] and [Encode] to plug in a different format.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use bytes::Bytes;
+    /// use lambda_runtime::{codec::RawCodec, Error, LambdaEvent, Runtime};
+    /// use tower::service_fn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Error> {
+    ///     let runtime = Runtime::new_with_codec(service_fn(func), RawCodec);
+    ///     runtime.run().await
+    /// }
+    ///
+    /// async fn func(event: LambdaEvent<Bytes>) -> Result<Bytes, Error> {
+    ///     Ok(event.payload)
+    /// }
+    /// ```
+    pub fn new_with_codec(handler: F, codec: Codec) -> Self {
+        Self::try_new_with_codec(handler, codec).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Create a new runtime with the provided codec, returning an error if the runtime
+    /// cannot be configured from the environment.
+    pub fn try_new_with_codec(handler: F, codec: Codec) -> Result<Self, RuntimeConfigError> {
+        let (config, client) = config_and_client_from_env()?;
+        Ok(Self::with_codec_and_client(handler, codec, config, client))
+    }
+
+    /// Set what the runtime does with buffered responses larger than the 6 MB payload limit
+    /// of synchronous invocations.
+    ///
+    /// By default, the runtime reports a `Function.ResponseSizeTooLarge` error instead of
+    /// sending the response. See [ResponseSizePolicy] for the alternatives.
+    pub fn with_response_size_policy(mut self, policy: ResponseSizePolicy) -> Self {
+        self.service.inner_mut().set_response_size_policy(policy);
+        self
+    }
+
+    /// Retry the requests to the Lambda Runtime API that fail with a transient error,
+    /// like a connection reset or a `5xx` response, according to the given policy.
+    ///
+    /// This is useful when running under local emulators, which can restart while
+    /// the runtime is polling for events.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use lambda_runtime::{Error, LambdaEvent, RetryPolicy, Runtime};
+    /// use serde_json::Value;
+    /// use std::time::Duration;
+    /// use tower::service_fn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Error> {
+    ///     let policy = RetryPolicy::new(5).with_max_backoff(Duration::from_secs(1));
+    ///     Runtime::new(service_fn(echo)).with_retry_policy(policy).run().await
+    /// }
+    ///
+    /// async fn echo(event: LambdaEvent<Value>) -> Result<Value, Error> {
+    ///     Ok(event.payload)
+    /// }
+    /// ```
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        // Keep the endpoint and transport of the current client.
+        let mut client = ApiClient::clone(&self.client);
+        client.retry_policy = Some(policy);
+        let client = Arc::new(client);
+        self.service.set_client(client.clone());
+        self.client = client;
+        self
+    }
+
+    /// Create a new runtime with the given codec, configuration and Runtime API client.
+    pub(crate) fn with_codec_and_client(handler: F, codec: Codec, config: Arc<Config>, client: Arc<ApiClient>) -> Self {
         Self {
-            service: wrap_handler(handler, client.clone()),
+            service: wrap_handler(handler, codec, client.clone()),
             config,
             client,
+            shutdown: future::pending().boxed(),
+            snapstart: SnapStartHooks::default(),
         }
     }
 }
@@ -133,6 +498,102 @@ impl<S> Runtime<S> {
             client: self.client,
             config: self.config,
             service: layer.layer(self.service),
+            shutdown: self.shutdown,
+            snapstart: self.snapstart,
+        }
+    }
+
+    /// Stop polling for new events once the given future completes.
+    ///
+    /// When the shutdown future completes, the runtime lets the invocation in progress finish,
+    /// and then [Runtime::run] returns `Ok(())`. Use the code after `run` to flush telemetry
+    /// exporters, close database pools, or release other resources.
+    ///
+    /// Use [shutdown_signal] to stop the runtime when Lambda shuts down the execution environment.
+    /// Note that Lambda only sends `SIGTERM` to the runtime process when the function has at
+    /// least one extension registered.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use lambda_runtime::{shutdown_signal, Error, LambdaEvent, Runtime};
+    /// use serde_json::Value;
+    /// use tower::service_fn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Error> {
+    ///     Runtime::new(service_fn(echo))
+    ///         .with_graceful_shutdown(shutdown_signal())
+    ///         .run()
+    ///         .await?;
+    ///
+    ///     // Flush exporters and close connections here.
+    ///     Ok(())
+    /// }
+    ///
+    /// async fn echo(event: LambdaEvent<Value>) -> Result<Value, Error> {
+    ///     Ok(event.payload)
+    /// }
+    /// ```
+    pub fn with_graceful_shutdown<F>(self, shutdown: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Runtime {
+            shutdown: shutdown.boxed(),
+            ..self
+        }
+    }
+
+    /// Run the given hooks around the snapshot of a Lambda SnapStart execution environment.
+    ///
+    /// When the function is initialized with SnapStart, the runtime calls `before_checkpoint`
+    /// once the function has been initialized, and waits on the Runtime API restore endpoint
+    /// while Lambda takes the snapshot. When the execution environment is restored from that
+    /// snapshot, the runtime calls `after_restore` before it starts polling for events. Use it
+    /// to re-seed random number generators, or to re-open network connections.
+    ///
+    /// A failure in `before_checkpoint` is reported as an initialization error, and a failure in
+    /// `after_restore` is reported to the Runtime API restore error endpoint. In both cases,
+    /// [Runtime::run] returns the error. The hooks are not called when the function is not
+    /// initialized with SnapStart. Handlers can check [Config::restored] through
+    /// [Context::env_config] to know whether their execution environment was restored.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use lambda_runtime::{Error, LambdaEvent, Runtime};
+    /// use serde_json::Value;
+    /// use tower::service_fn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Error> {
+    ///     Runtime::new(service_fn(echo))
+    ///         .with_snapstart_hooks(
+    ///             || async { Ok::<_, Error>(()) },
+    ///             || async {
+    ///                 // Re-open connections here.
+    ///                 Ok::<_, Error>(())
+    ///             },
+    ///         )
+    ///         .run()
+    ///         .await
+    /// }
+    ///
+    /// async fn echo(event: LambdaEvent<Value>) -> Result<Value, Error> {
+    ///     Ok(event.payload)
+    /// }
+    /// ```
+    pub fn with_snapstart_hooks<B, BFut, BErr, A, AFut, AErr>(self, before_checkpoint: B, after_restore: A) -> Self
+    where
+        B: FnOnce() -> BFut + Send + 'static,
+        BFut: Future<Output = Result<(), BErr>> + Send + 'static,
+        BErr: Into<Diagnostic<'static>> + Debug,
+        A: FnOnce() -> AFut + Send + 'static,
+        AFut: Future<Output = Result<(), AErr>> + Send + 'static,
+        AErr: Into<Diagnostic<'static>> + Debug,
+    {
+        Runtime {
+            snapstart: SnapStartHooks::new(before_checkpoint, after_restore),
+            ..self
         }
     }
 }
@@ -143,8 +604,9 @@ where
 {
     /// Start the runtime and begin polling for events on the Lambda Runtime API.
     pub async fn run(self) -> Result<(), BoxError> {
-        let incoming = incoming(&self.client);
-        Self::run_with_incoming(self.service, self.config, incoming).await
+        let config = snapstart_restore(&self.client, self.config, self.snapstart).await?;
+        let incoming = futures::StreamExt::take_until(incoming(self.client.clone()), self.shutdown);
+        Self::run_with_incoming(self.service, config, self.client, incoming).await
     }
 
     /// Internal utility function to start the runtime with a customized incoming stream.
@@ -152,27 +614,16 @@ where
     pub(crate) async fn run_with_incoming(
         mut service: S,
         config: Arc<Config>,
+        client: Arc<ApiClient>,
         incoming: impl Stream<Item = Result<http::Response<hyper::body::Incoming>, BoxError>> + Send,
     ) -> Result<(), BoxError> {
         tokio::pin!(incoming);
         while let Some(next_event_response) = incoming.next().await {
             trace!("New event arrived (run loop)");
-            let event = next_event_response?;
-            let (parts, incoming) = event.into_parts();
-
-            #[cfg(debug_assertions)]
-            if parts.status == http::StatusCode::NO_CONTENT {
-                // Ignore the event if the status code is 204.
-                // This is a way to keep the runtime alive when
-                // there are no events pending to be processed.
-                continue;
-            }
-
-            // Build the invocation such that it can be sent to the service right away
-            // when it is ready
-            let body = incoming.collect().await?.to_bytes();
-            let context = Context::new(invoke_request_id(&parts.headers)?, config.clone(), &parts.headers)?;
-            let invocation = LambdaInvocation { parts, body, context };
+            let invocation = match build_invocation(next_event_response?, &config, &client).await? {
+                Some(invocation) => invocation,
+                None => continue,
+            };
 
             // Setup Amazon's default tracing data
             amzn_trace_env(&invocation.context);
@@ -187,11 +638,111 @@ where
     }
 }
 
+impl<S> Runtime<S>
+where
+    S: Service<LambdaInvocation, Response = (), Error = BoxError> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    /// Start the runtime and process invocations concurrently.
+    ///
+    /// The maximum number of in-flight invocations is read from the `AWS_LAMBDA_MAX_CONCURRENCY`
+    /// environment variable. The runtime starts that many workers, each one polling the Lambda
+    /// Runtime API for events and calling its own clone of the service. If the variable is not
+    /// set, or its value is lower than 2, this method behaves exactly like [Runtime::run].
+    ///
+    /// In concurrent mode, the runtime doesn't set the process-wide `_X_AMZN_TRACE_ID`
+    /// environment variable, since several invocations share the same process. Use
+    /// [xray_trace_id] to get the trace id of the invocation processed by the current task.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use lambda_runtime::{Error, LambdaEvent, Runtime};
+    /// use serde_json::Value;
+    /// use tower::service_fn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Error> {
+    ///     Runtime::new(service_fn(echo)).run_concurrent().await
+    /// }
+    ///
+    /// async fn echo(event: LambdaEvent<Value>) -> Result<Value, Error> {
+    ///     Ok(event.payload)
+    /// }
+    /// ```
+    pub async fn run_concurrent(self) -> Result<(), BoxError> {
+        match max_concurrency_from_env() {
+            Some(limit) if limit > 1 => {
+                let config = snapstart_restore(&self.client, self.config, self.snapstart).await?;
+                let client = self.client.clone();
+                let shutdown = self.shutdown.shared();
+                let make_incoming = move || futures::StreamExt::take_until(incoming(client.clone()), shutdown.clone());
+                Self::run_concurrent_with_incoming(self.service, config, self.client, limit, make_incoming).await
+            }
+            _ => self.run().await,
+        }
+    }
+
+    /// Internal utility function to start `limit` concurrent workers, each of them
+    /// processing events from its own incoming stream.
+    /// This implements the core of the [Runtime::run_concurrent] method.
+    pub(crate) async fn run_concurrent_with_incoming<I>(
+        service: S,
+        config: Arc<Config>,
+        client: Arc<ApiClient>,
+        limit: usize,
+        make_incoming: impl Fn() -> I,
+    ) -> Result<(), BoxError>
+    where
+        I: Stream<Item = Result<http::Response<hyper::body::Incoming>, BoxError>> + Send + 'static,
+    {
+        trace!(limit, "Starting concurrent workers");
+        let mut workers = JoinSet::new();
+        for _ in 0..limit {
+            workers.spawn(Self::run_worker(
+                service.clone(),
+                config.clone(),
+                client.clone(),
+                make_incoming(),
+            ));
+        }
+
+        while let Some(result) = workers.join_next().await {
+            result??;
+        }
+        Ok(())
+    }
+
+    async fn run_worker(
+        mut service: S,
+        config: Arc<Config>,
+        client: Arc<ApiClient>,
+        incoming: impl Stream<Item = Result<http::Response<hyper::body::Incoming>, BoxError>> + Send,
+    ) -> Result<(), BoxError> {
+        tokio::pin!(incoming);
+        while let Some(next_event_response) = incoming.next().await {
+            trace!("New event arrived (worker loop)");
+            let invocation = match build_invocation(next_event_response?, &config, &client).await? {
+                Some(invocation) => invocation,
+                None => continue,
+            };
+
+            // Keep the tracing data in the task instead of the process environment,
+            // which is shared with other in-flight invocations.
+            let trace_id = invocation.context.xray_trace_id.clone();
+
+            let ready = service.ready().await?;
+            XRAY_TRACE_ID.scope(trace_id, ready.call(invocation)).await?;
+        }
+        Ok(())
+    }
+}
+
 /* ------------------------------------------- UTILS ------------------------------------------- */
 
 #[allow(clippy::type_complexity)]
-fn wrap_handler<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>(
+fn wrap_handler<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, Codec>(
     handler: F,
+    codec: Codec,
     client: Arc<ApiClient>,
 ) -> RuntimeApiClientService<
     RuntimeApiResponseService<
@@ -202,27 +753,231 @@ fn wrap_handler<'a, F, EventPayload, Response, BufferedResponse, StreamingRespon
         StreamingResponse,
         StreamItem,
         StreamError,
+        Codec,
     >,
 >
 where
     F: Service<LambdaEvent<EventPayload>, Response = Response>,
     F::Future: Future<Output = Result<Response, F::Error>>,
     F::Error: Into<Diagnostic<'a>> + Debug,
-    EventPayload: for<'de> Deserialize<'de>,
     Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
-    BufferedResponse: Serialize,
+    Codec: Decode<EventPayload> + Encode<BufferedResponse> + Clone,
     StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
     StreamItem: Into<bytes::Bytes> + Send,
     StreamError: Into<BoxError> + Send + Debug,
 {
     let safe_service = CatchPanicService::new(handler);
-    let response_service = RuntimeApiResponseService::new(safe_service);
+    let response_service = RuntimeApiResponseService::new(safe_service, codec);
     RuntimeApiClientService::new(response_service, client)
 }
 
-fn incoming(
+/// Run the init function of a handler, and report any failure to the Runtime API init error endpoint.
+async fn run_init<'a, Init, InitFuture, F, InitError>(client: &ApiClient, init: Init) -> Result<F, BoxError>
+where
+    Init: FnOnce() -> InitFuture,
+    InitFuture: Future<Output = Result<F, InitError>>,
+    InitError: Into<Diagnostic<'a>> + Debug,
+{
+    let err = match init().await {
+        Ok(handler) => return Ok(handler),
+        Err(err) => err,
+    };
+
+    error!(error = ?err, "function initialization failed");
+    let diagnostic: Diagnostic<'a> = err.into();
+    let message = format!("{}: {}", diagnostic.error_type, diagnostic.error_message);
+
+    let req = InitErrorRequest::new(diagnostic).into_req()?;
+    if let Err(err) = client.call(req).await {
+        error!(error = ?err, "failed to send init error to Lambda Runtime API");
+    }
+    Err(message.into())
+}
+
+type SnapStartHook = Box<dyn FnOnce() -> BoxFuture<'static, Result<(), Box<Diagnostic<'static>>>> + Send>;
+
+/// Hooks called around the snapshot of a SnapStart execution environment.
+#[derive(Default)]
+struct SnapStartHooks {
+    before_checkpoint: Option<SnapStartHook>,
+    after_restore: Option<SnapStartHook>,
+}
+
+impl SnapStartHooks {
+    fn new<B, BFut, BErr, A, AFut, AErr>(before_checkpoint: B, after_restore: A) -> Self
+    where
+        B: FnOnce() -> BFut + Send + 'static,
+        BFut: Future<Output = Result<(), BErr>> + Send + 'static,
+        BErr: Into<Diagnostic<'static>> + Debug,
+        A: FnOnce() -> AFut + Send + 'static,
+        AFut: Future<Output = Result<(), AErr>> + Send + 'static,
+        AErr: Into<Diagnostic<'static>> + Debug,
+    {
+        let before_checkpoint: SnapStartHook = Box::new(move || {
+            before_checkpoint()
+                .map(|result| {
+                    result.map_err(|err| {
+                        error!(error = ?err, "SnapStart before-checkpoint hook failed");
+                        Box::new(err.into())
+                    })
+                })
+                .boxed()
+        });
+        let after_restore: SnapStartHook = Box::new(move || {
+            after_restore()
+                .map(|result| {
+                    result.map_err(|err| {
+                        error!(error = ?err, "SnapStart after-restore hook failed");
+                        Box::new(err.into())
+                    })
+                })
+                .boxed()
+        });
+        Self {
+            before_checkpoint: Some(before_checkpoint),
+            after_restore: Some(after_restore),
+        }
+    }
+}
+
+/// Drive the restore phase of the Runtime API when the execution environment is initialized
+/// with SnapStart, and return the configuration to use once the environment is restored.
+async fn snapstart_restore(
+    client: &ApiClient,
+    config: Arc<Config>,
+    hooks: SnapStartHooks,
+) -> Result<Arc<Config>, BoxError> {
+    if config.initialization_type != InitializationType::SnapStart {
+        return Ok(config);
+    }
+
+    if let Some(before_checkpoint) = hooks.before_checkpoint {
+        if let Err(diagnostic) = before_checkpoint().await {
+            let message = format!("{}: {}", diagnostic.error_type, diagnostic.error_message);
+            let req = InitErrorRequest::new(*diagnostic).into_req()?;
+            if let Err(err) = client.call(req).await {
+                error!(error = ?err, "failed to send init error to Lambda Runtime API");
+            }
+            return Err(message.into());
+        }
+    }
+
+    // Lambda takes the snapshot while this request is pending,
+    // and responds to it once the execution environment is restored.
+    trace!("Waiting for the execution environment to be restored");
+    client.call(RestoreNextRequest.into_req()?).await?;
+
+    if let Some(after_restore) = hooks.after_restore {
+        if let Err(diagnostic) = after_restore().await {
+            let message = format!("{}: {}", diagnostic.error_type, diagnostic.error_message);
+            let req = RestoreErrorRequest::new(*diagnostic).into_req()?;
+            if let Err(err) = client.call(req).await {
+                error!(error = ?err, "failed to send restore error to Lambda Runtime API");
+            }
+            return Err(message.into());
+        }
+    }
+
+    Ok(Arc::new(Config {
+        restored: true,
+        ..Config::clone(&config)
+    }))
+}
+
+/// Build the invocation from the response of the Runtime API such that it can be sent to
+/// the service right away when it is ready.
+///
+/// Invocations with missing or invalid headers are reported to the Runtime API as invocation
+/// errors, and skipped, so a single malformed event doesn't stop the runtime. Invocations
+/// without a valid request id can't be reported, because the error endpoint is per request,
+/// so they are reported as initialization errors and the runtime exits.
+async fn build_invocation(
+    event: http::Response<hyper::body::Incoming>,
+    config: &Arc<Config>,
+    client: &Arc<ApiClient>,
+) -> Result<Option<LambdaInvocation>, BoxError> {
+    let (mut parts, incoming) = event.into_parts();
+
+    #[cfg(debug_assertions)]
+    if parts.status == http::StatusCode::NO_CONTENT {
+        // Ignore the event if the status code is 204.
+        // This is a way to keep the runtime alive when
+        // there are no events pending to be processed.
+        return Ok(None);
+    }
+
+    let timings = InvocationTimings::default();
+    if let Some(CallLatency(latency)) = parts.extensions.get() {
+        timings.set_next_event(*latency);
+    }
+    let start = Instant::now();
+    let body = incoming.collect().await?.to_bytes();
+    timings.set_payload_download(start.elapsed());
+
+    let context = NextInvocation::from_headers(&parts.headers)
+        .map_err(BoxError::from)
+        .and_then(|invocation| Context::from_invocation(invocation, config.clone()));
+    let context = match context {
+        Ok(context) => context,
+        Err(err) => return reject_invocation(&parts.headers, client, err).await,
+    };
+    parts.extensions.insert(InvocationClient(client.clone()));
+    Ok(Some(LambdaInvocation {
+        parts,
+        body,
+        context,
+        timings,
+    }))
+}
+
+/// Report an invocation that the runtime can't process.
+///
+/// Without a request id, the invocation can't be answered, so the error is reported as an
+/// initialization error and the runtime stops. Otherwise, only the invocation fails.
+async fn reject_invocation(
+    headers: &http::HeaderMap,
     client: &ApiClient,
-) -> impl Stream<Item = Result<http::Response<hyper::body::Incoming>, BoxError>> + Send + '_ {
+    err: BoxError,
+) -> Result<Option<LambdaInvocation>, BoxError> {
+    let diagnostic = Diagnostic {
+        error_type: Cow::Borrowed("Runtime.InvalidInvocation"),
+        error_message: Cow::Owned(err.to_string()),
+        ..Default::default()
+    };
+
+    let Ok(request_id) = invoke_request_id(headers) else {
+        error!(error = ?err, "received an invocation without a valid request id");
+        let req = InitErrorRequest::new(diagnostic).into_req()?;
+        if let Err(err) = client.call(req).await {
+            error!(error = ?err, "failed to send init error to Lambda Runtime API");
+        }
+        return Err(err);
+    };
+
+    error!(error = ?err, request_id, "failed to build the context of the invocation");
+    let xray_trace_id = headers.get(TRACE_ID_HEADER).and_then(|v| v.to_str().ok());
+    let req = EventErrorRequest::new(request_id, diagnostic)
+        .with_xray_trace_id(xray_trace_id)
+        .into_req()?;
+    if let Err(err) = client.call(req).await {
+        error!(error = ?err, "failed to send invocation error to Lambda Runtime API");
+    }
+    Ok(None)
+}
+
+/// Load the runtime configuration and create the Runtime API client from the environment.
+fn config_and_client_from_env() -> Result<(Arc<Config>, Arc<ApiClient>), RuntimeConfigError> {
+    trace!("Loading config from env");
+    let config = Config::try_from_env()?;
+    let client = ApiClient::builder()
+        .build()
+        .map_err(|e| RuntimeConfigError::Client(e.into()))?;
+    Ok((Arc::new(config), Arc::new(client)))
+}
+
+fn incoming(
+    client: Arc<ApiClient>,
+) -> impl Stream<Item = Result<http::Response<hyper::body::Incoming>, BoxError>> + Send + 'static {
     async_stream::stream! {
         loop {
             trace!("Waiting for next event (incoming loop)");
@@ -233,6 +988,51 @@ fn incoming(
     }
 }
 
+fn max_concurrency_from_env() -> Option<usize> {
+    env::var(MAX_CONCURRENCY_ENV_VAR).ok()?.parse().ok()
+}
+
+tokio::task_local! {
+    static XRAY_TRACE_ID: Option<String>;
+}
+
+/// Return the X-Ray trace id of the invocation that the current task is processing.
+///
+/// When the runtime processes invocations concurrently with [Runtime::run_concurrent], the trace
+/// id is kept in the task that calls the handler. Otherwise, this function reads the trace id
+/// from the `_X_AMZN_TRACE_ID` environment variable.
+pub fn xray_trace_id() -> Option<String> {
+    XRAY_TRACE_ID
+        .try_with(|trace_id| trace_id.clone())
+        .unwrap_or_else(|_| env::var("_X_AMZN_TRACE_ID").ok())
+}
+
+/// Wait for the runtime process to receive a `SIGTERM` or `SIGINT` signal.
+///
+/// Lambda sends `SIGTERM` to the runtime process before shutting down the execution environment,
+/// if the function has at least one extension registered. Pass this future to
+/// [Runtime::with_graceful_shutdown] to stop processing events when that happens.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+        let mut interrupt = signal(SignalKind::interrupt()).expect("Unable to listen for SIGINT");
+        tokio::select! {
+            _ = terminate.recv() => trace!("SIGTERM received"),
+            _ = interrupt.recv() => trace!("SIGINT received"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            trace!("Ctrl-C received");
+        }
+    }
+}
+
 fn amzn_trace_env(ctx: &Context) {
     match &ctx.xray_trace_id {
         Some(trace_id) => env::set_var("_X_AMZN_TRACE_ID", trace_id),
@@ -246,50 +1046,72 @@ fn amzn_trace_env(ctx: &Context) {
 
 #[cfg(test)]
 mod endpoint_tests {
-    use super::{incoming, wrap_handler};
+    use super::{incoming, run_init, snapstart_restore, wrap_handler, InvocationClient, SnapStartHooks};
     use crate::{
+        codec::{JsonCodec, RawCodec},
         requests::{EventCompletionRequest, EventErrorRequest, IntoRequest, NextEventRequest},
-        Config, Diagnostic, Error, Runtime,
+        testing::{FakeRuntimeApi, TestEvent, TestInvocation, TestOutcome},
+        Config, Diagnostic, Error, InitializationType, InvocationTimings, LambdaInvocation, Runtime,
     };
     use futures::future::BoxFuture;
     use http::{HeaderValue, StatusCode};
     use http_body_util::BodyExt;
-    use httpmock::prelude::*;
 
-    use lambda_runtime_api_client::Client;
-    use std::{borrow::Cow, env, sync::Arc};
+    use lambda_runtime_api_client::RetryPolicy;
+    use std::{
+        borrow::Cow,
+        env,
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
     use tokio_stream::StreamExt;
+    use tower::util::MapRequestLayer;
+
+    const REQUEST_ID: &str = "156cb537-e2d4-11e8-9b34-d36013741fb9";
+
+    fn test_config() -> Config {
+        Config::builder()
+            .with_function_name("test_fn")
+            .with_memory(128)
+            .with_version("1")
+            .with_log_stream("test_stream")
+            .with_log_group("test_log")
+            .build()
+    }
+
+    fn response_body(invocation: &TestInvocation) -> &[u8] {
+        match &invocation.outcome {
+            TestOutcome::Response(body) => body,
+            outcome => panic!("unexpected outcome: {outcome:?}"),
+        }
+    }
+
+    fn error_diagnostic(invocation: &TestInvocation) -> &Diagnostic<'static> {
+        match &invocation.outcome {
+            TestOutcome::Error { diagnostic, .. } => diagnostic,
+            outcome => panic!("unexpected outcome: {outcome:?}"),
+        }
+    }
 
     #[tokio::test]
     async fn test_next_event() -> Result<(), Error> {
-        let server = MockServer::start();
-        let request_id = "156cb537-e2d4-11e8-9b34-d36013741fb9";
-        let deadline = "1542409706888";
-
-        let mock = server.mock(|when, then| {
-            when.method(GET).path("/2018-06-01/runtime/invocation/next");
-            then.status(200)
-                .header("content-type", "application/json")
-                .header("lambda-runtime-aws-request-id", request_id)
-                .header("lambda-runtime-deadline-ms", deadline)
-                .body("{}");
-        });
+        let api = FakeRuntimeApi::start().await?;
+        let deadline = SystemTime::UNIX_EPOCH + Duration::from_millis(1542409706888);
+        api.enqueue(TestEvent::new("{}").with_request_id(REQUEST_ID).with_deadline(deadline));
 
-        let base = server.base_url().parse().expect("Invalid mock server Uri");
-        let client = Client::builder().with_endpoint(base).build()?;
+        let client = api.client()?;
 
         let req = NextEventRequest.into_req()?;
         let rsp = client.call(req).await.expect("Unable to send request");
 
-        mock.assert_async().await;
         assert_eq!(rsp.status(), StatusCode::OK);
         assert_eq!(
             rsp.headers()["lambda-runtime-aws-request-id"],
-            &HeaderValue::from_static(request_id)
+            &HeaderValue::from_static(REQUEST_ID)
         );
         assert_eq!(
             rsp.headers()["lambda-runtime-deadline-ms"],
-            &HeaderValue::from_static(deadline)
+            &HeaderValue::from_static("1542409706888")
         );
 
         let body = rsp.into_body().collect().await?.to_bytes();
@@ -299,25 +1121,18 @@ mod endpoint_tests {
 
     #[tokio::test]
     async fn test_ok_response() -> Result<(), Error> {
-        let server = MockServer::start();
-
-        let mock = server.mock(|when, then| {
-            when.method(POST)
-                .path("/2018-06-01/runtime/invocation/156cb537-e2d4-11e8-9b34-d36013741fb9/response")
-                .body("\"{}\"");
-            then.status(200).body("");
-        });
+        let api = FakeRuntimeApi::start().await?;
+        let client = api.client()?;
 
-        let base = server.base_url().parse().expect("Invalid mock server Uri");
-        let client = Client::builder().with_endpoint(base).build()?;
-
-        let req = EventCompletionRequest::new("156cb537-e2d4-11e8-9b34-d36013741fb9", "{}");
+        let req = EventCompletionRequest::new(REQUEST_ID, "{}");
         let req = req.into_req()?;
 
         let rsp = client.call(req).await?;
 
-        mock.assert_async().await;
-        assert_eq!(rsp.status(), StatusCode::OK);
+        assert_eq!(rsp.status(), StatusCode::ACCEPTED);
+        let invocations = api.take_invocations();
+        assert_eq!(REQUEST_ID, invocations[0].request_id);
+        assert_eq!(b"\"{}\"", response_body(&invocations[0]));
         Ok(())
     }
 
@@ -326,56 +1141,63 @@ mod endpoint_tests {
         let diagnostic = Diagnostic {
             error_type: Cow::Borrowed("InvalidEventDataError"),
             error_message: Cow::Borrowed("Error parsing event data"),
+            ..Default::default()
         };
-        let body = serde_json::to_string(&diagnostic)?;
-
-        let server = MockServer::start();
-        let mock = server.mock(|when, then| {
-            when.method(POST)
-                .path("/2018-06-01/runtime/invocation/156cb537-e2d4-11e8-9b34-d36013741fb9/error")
-                .header("lambda-runtime-function-error-type", "unhandled")
-                .body(body);
-            then.status(200).body("");
-        });
 
-        let base = server.base_url().parse().expect("Invalid mock server Uri");
-        let client = Client::builder().with_endpoint(base).build()?;
+        let api = FakeRuntimeApi::start().await?;
+        let client = api.client()?;
 
         let req = EventErrorRequest {
-            request_id: "156cb537-e2d4-11e8-9b34-d36013741fb9",
-            diagnostic,
+            request_id: REQUEST_ID,
+            xray_trace_id: None,
+            diagnostic: diagnostic.clone(),
         };
         let req = req.into_req()?;
         let rsp = client.call(req).await?;
 
-        mock.assert_async().await;
-        assert_eq!(rsp.status(), StatusCode::OK);
+        assert_eq!(rsp.status(), StatusCode::ACCEPTED);
+        let invocations = api.take_invocations();
+        assert_eq!(REQUEST_ID, invocations[0].request_id);
+        assert_eq!(&diagnostic, error_diagnostic(&invocations[0]));
         Ok(())
     }
 
     #[tokio::test]
-    async fn successful_end_to_end_run() -> Result<(), Error> {
-        let server = MockServer::start();
-        let request_id = "156cb537-e2d4-11e8-9b34-d36013741fb9";
-        let deadline = "1542409706888";
-
-        let next_request = server.mock(|when, then| {
-            when.method(GET).path("/2018-06-01/runtime/invocation/next");
-            then.status(200)
-                .header("content-type", "application/json")
-                .header("lambda-runtime-aws-request-id", request_id)
-                .header("lambda-runtime-deadline-ms", deadline)
-                .body("{}");
-        });
-        let next_response = server.mock(|when, then| {
-            when.method(POST)
-                .path(format!("/2018-06-01/runtime/invocation/{}/response", request_id))
-                .body("{}");
-            then.status(200).body("");
-        });
+    async fn init_error_is_reported() -> Result<(), Error> {
+        let diagnostic = Diagnostic {
+            error_type: Cow::Borrowed("InitError"),
+            error_message: Cow::Borrowed("Unable to load secrets"),
+            ..Default::default()
+        };
+
+        let api = FakeRuntimeApi::start().await?;
+        let client = api.client()?;
+
+        let init_error = diagnostic.clone();
+        let result = run_init(&client, || async { Err::<(), _>(init_error) }).await;
+
+        let err = result.expect_err("init should fail");
+        assert_eq!("InitError: Unable to load secrets", err.to_string());
+        assert_eq!(vec![diagnostic], api.init_errors());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn successful_init_is_not_reported() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        let client = api.client()?;
+
+        let handler = run_init(&client, || async { Ok::<_, Error>("handler") }).await?;
+
+        assert!(api.init_errors().is_empty());
+        assert_eq!("handler", handler);
+        Ok(())
+    }
 
-        let base = server.base_url().parse().expect("Invalid mock server Uri");
-        let client = Client::builder().with_endpoint(base).build()?;
+    #[tokio::test]
+    async fn successful_end_to_end_run() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::new("{}").with_request_id(REQUEST_ID));
 
         async fn func(event: crate::LambdaEvent<serde_json::Value>) -> Result<serde_json::Value, Error> {
             let (event, _) = event.into_parts();
@@ -384,9 +1206,6 @@ mod endpoint_tests {
         let f = crate::service_fn(func);
 
         // set env vars needed to init Config if they are not already set in the environment
-        if env::var("AWS_LAMBDA_RUNTIME_API").is_err() {
-            env::set_var("AWS_LAMBDA_RUNTIME_API", server.base_url());
-        }
         if env::var("AWS_LAMBDA_FUNCTION_NAME").is_err() {
             env::set_var("AWS_LAMBDA_FUNCTION_NAME", "test_fn");
         }
@@ -404,18 +1223,67 @@ mod endpoint_tests {
         }
         let config = Config::from_env();
 
-        let client = Arc::new(client);
-        let runtime = Runtime {
-            client: client.clone(),
-            config: Arc::new(config),
-            service: wrap_handler(f, client),
-        };
-        let client = &runtime.client;
-        let incoming = incoming(client).take(1);
-        Runtime::run_with_incoming(runtime.service, runtime.config, incoming).await?;
+        let invocations = api.run(Runtime::with_client(f, config, api.client()?)).await?;
 
-        next_request.assert_async().await;
-        next_response.assert_async().await;
+        assert_eq!(REQUEST_ID, invocations[0].request_id);
+        assert_eq!(b"{}", response_body(&invocations[0]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invocation_timings_are_readable_from_layers() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::new("{}"));
+
+        async fn func(event: crate::LambdaEvent<serde_json::Value>) -> Result<serde_json::Value, Error> {
+            Ok(event.payload)
+        }
+
+        let captured: Arc<Mutex<Option<InvocationTimings>>> = Default::default();
+        let capture = captured.clone();
+        let runtime = Runtime::with_client(crate::service_fn(func), Config::default(), api.client()?).layer(
+            MapRequestLayer::new(move |invocation: LambdaInvocation| {
+                *capture.lock().unwrap() = Some(invocation.timings.clone());
+                invocation
+            }),
+        );
+        // Run the invocation to completion, the timings are recorded after the response is sent.
+        let incoming = incoming(runtime.client.clone()).take(1);
+        Runtime::run_with_incoming(runtime.service, runtime.config, runtime.client, incoming).await?;
+        assert_eq!(1, api.take_invocations().len());
+
+        let timings = captured.lock().unwrap().take().expect("the layer was not called");
+        let upload = timings.response_upload().expect("missing response upload timing");
+        assert!(timings.error_report().is_none());
+        assert!(timings.next_event() > Duration::ZERO);
+        assert_eq!(timings.payload_download() + upload, timings.overhead());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invocations_carry_the_retrying_client() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::new("{}"));
+
+        async fn func(event: crate::LambdaEvent<serde_json::Value>) -> Result<serde_json::Value, Error> {
+            Ok(event.payload)
+        }
+
+        // Layers like DeadlineLayer report errors with the client stored in the invocation,
+        // so it must be the one configured with the retry policy.
+        let policy = RetryPolicy::new(2);
+        let captured: Arc<Mutex<Option<RetryPolicy>>> = Default::default();
+        let capture = captured.clone();
+        let runtime = Runtime::with_client(crate::service_fn(func), Config::default(), api.client()?)
+            .with_retry_policy(policy.clone())
+            .layer(MapRequestLayer::new(move |invocation: LambdaInvocation| {
+                let InvocationClient(client) = invocation.parts.extensions.get().expect("missing invocation client");
+                *capture.lock().unwrap() = client.retry_policy.clone();
+                invocation
+            }));
+        api.run(runtime).await?;
+
+        assert_eq!(Some(policy), captured.lock().unwrap().take());
         Ok(())
     }
 
@@ -425,51 +1293,209 @@ mod endpoint_tests {
             + Send
             + 'static,
     {
-        let server = MockServer::start();
-        let request_id = "156cb537-e2d4-11e8-9b34-d36013741fb9";
-        let deadline = "1542409706888";
-
-        let next_request = server.mock(|when, then| {
-            when.method(GET).path("/2018-06-01/runtime/invocation/next");
-            then.status(200)
-                .header("content-type", "application/json")
-                .header("lambda-runtime-aws-request-id", request_id)
-                .header("lambda-runtime-deadline-ms", deadline)
-                .body("{}");
-        });
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::new("{}").with_request_id(REQUEST_ID));
 
-        let next_response = server.mock(|when, then| {
-            when.method(POST)
-                .path(format!("/2018-06-01/runtime/invocation/{}/error", request_id))
-                .header("lambda-runtime-function-error-type", "unhandled");
-            then.status(200).body("");
-        });
+        let f = crate::service_fn(func);
+
+        let invocations = api.run(Runtime::with_client(f, test_config(), api.client()?)).await?;
+
+        assert_eq!(REQUEST_ID, invocations[0].request_id);
+        assert!(matches!(invocations[0].outcome, TestOutcome::Error { .. }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_end_to_end_run() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        let trace_id = "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+        api.enqueue(TestEvent::new("{}").with_xray_trace_id(trace_id)?);
+        api.enqueue(TestEvent::new("{}").with_xray_trace_id(trace_id)?);
 
-        let base = server.base_url().parse().expect("Invalid mock server Uri");
-        let client = Client::builder().with_endpoint(base).build()?;
+        let client = Arc::new(api.client()?);
 
+        async fn func(_event: crate::LambdaEvent<serde_json::Value>) -> Result<Option<String>, Error> {
+            Ok(crate::xray_trace_id())
+        }
         let f = crate::service_fn(func);
 
-        let config = Arc::new(Config {
-            function_name: "test_fn".to_string(),
-            memory: 128,
-            version: "1".to_string(),
-            log_stream: "test_stream".to_string(),
-            log_group: "test_log".to_string(),
-        });
+        let service = wrap_handler(f, JsonCodec, client.clone());
+        let make_incoming = {
+            let client = client.clone();
+            move || incoming(client.clone()).take(1)
+        };
+        Runtime::run_concurrent_with_incoming(service, Arc::new(test_config()), client, 2, make_incoming).await?;
 
-        let client = Arc::new(client);
-        let runtime = Runtime {
-            client: client.clone(),
-            config,
-            service: wrap_handler(f, client),
+        let invocations = api.take_invocations();
+        assert_eq!(2, invocations.len());
+        for invocation in &invocations {
+            assert_eq!(format!("\"{trace_id}\"").as_bytes(), response_body(invocation));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invocation_with_invalid_deadline_is_reported() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(
+            TestEvent::new("{}")
+                .with_request_id(REQUEST_ID)
+                .with_header("lambda-runtime-deadline-ms", "soon")?,
+        );
+
+        let client = Arc::new(api.client()?);
+
+        async fn func(event: crate::LambdaEvent<serde_json::Value>) -> Result<serde_json::Value, Error> {
+            Ok(event.payload)
+        }
+        let f = crate::service_fn(func);
+
+        let service = wrap_handler(f, JsonCodec, client.clone());
+        Runtime::run_with_incoming(
+            service,
+            Arc::new(test_config()),
+            client.clone(),
+            incoming(client).take(1),
+        )
+        .await?;
+
+        let invocations = api.take_invocations();
+        assert_eq!(REQUEST_ID, invocations[0].request_id);
+        let diagnostic = error_diagnostic(&invocations[0]);
+        assert_eq!("Runtime.InvalidInvocation", diagnostic.error_type);
+        assert_eq!(
+            "invalid lambda-runtime-deadline-ms header: soon",
+            diagnostic.error_message
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn snapstart_hooks_run_around_restore() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        let client = api.client()?;
+        let config = Arc::new(
+            Config::builder()
+                .with_initialization_type(InitializationType::SnapStart)
+                .build(),
+        );
+
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (before_calls, after_calls) = (calls.clone(), calls.clone());
+        let hooks = SnapStartHooks::new(
+            move || async move {
+                before_calls.lock().unwrap().push("before_checkpoint");
+                Ok::<_, Error>(())
+            },
+            move || async move {
+                after_calls.lock().unwrap().push("after_restore");
+                Ok::<_, Error>(())
+            },
+        );
+
+        let config = snapstart_restore(&client, config, hooks).await?;
+
+        assert!(config.restored);
+        assert_eq!(vec!["before_checkpoint", "after_restore"], *calls.lock().unwrap());
+        assert!(api.restore_errors().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn snapstart_restore_error_is_reported() -> Result<(), Error> {
+        let diagnostic = Diagnostic {
+            error_type: Cow::Borrowed("RestoreError"),
+            error_message: Cow::Borrowed("Unable to reconnect"),
+            ..Default::default()
         };
-        let client = &runtime.client;
-        let incoming = incoming(client).take(1);
-        Runtime::run_with_incoming(runtime.service, runtime.config, incoming).await?;
 
-        next_request.assert_async().await;
-        next_response.assert_async().await;
+        let api = FakeRuntimeApi::start().await?;
+        let client = api.client()?;
+        let config = Arc::new(
+            Config::builder()
+                .with_initialization_type(InitializationType::SnapStart)
+                .build(),
+        );
+
+        let restore_error = diagnostic.clone();
+        let hooks = SnapStartHooks::new(|| async { Ok::<_, Error>(()) }, || async { Err(restore_error) });
+
+        let err = snapstart_restore(&client, config, hooks).await.unwrap_err();
+        assert_eq!("RestoreError: Unable to reconnect", err.to_string());
+        assert_eq!(vec![diagnostic], api.restore_errors());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn snapstart_restore_is_skipped_on_demand() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        let client = api.client()?;
+        let config = Arc::new(Config::default());
+
+        let hooks = SnapStartHooks::new(
+            || async { Err::<(), _>(Error::from("must not be called")) },
+            || async { Err::<(), _>(Error::from("must not be called")) },
+        );
+
+        let config = snapstart_restore(&client, config, hooks).await?;
+
+        assert!(!config.restored);
+        assert!(api.restore_errors().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn raw_codec_end_to_end_run() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(
+            TestEvent::new("not json")
+                .with_request_id(REQUEST_ID)
+                .with_header("content-type", "application/octet-stream")?,
+        );
+
+        let client = Arc::new(api.client()?);
+
+        async fn func(event: crate::LambdaEvent<bytes::Bytes>) -> Result<bytes::Bytes, Error> {
+            Ok(event.payload.to_ascii_uppercase().into())
+        }
+        let f = crate::service_fn(func);
+
+        let service = wrap_handler(f, RawCodec, client.clone());
+        Runtime::run_with_incoming(
+            service,
+            Arc::new(test_config()),
+            client.clone(),
+            incoming(client).take(1),
+        )
+        .await?;
+
+        let invocations = api.take_invocations();
+        assert_eq!(REQUEST_ID, invocations[0].request_id);
+        assert_eq!(b"NOT JSON", response_body(&invocations[0]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown_finishes_in_flight_invocation() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::new("{}").with_request_id(REQUEST_ID));
+
+        // The handler requests the shutdown, which must not interrupt the invocation in progress.
+        let shutdown = Arc::new(tokio::sync::Notify::new());
+        let handler_shutdown = shutdown.clone();
+        let f = crate::service_fn(move |event: crate::LambdaEvent<serde_json::Value>| {
+            handler_shutdown.notify_one();
+            async move { Ok::<_, Error>(event.payload) }
+        });
+
+        Runtime::with_client(f, test_config(), api.client()?)
+            .with_graceful_shutdown(async move { shutdown.notified().await })
+            .run()
+            .await?;
+
+        let invocations = api.take_invocations();
+        assert_eq!(1, invocations.len());
+        assert_eq!(b"{}", response_body(&invocations[0]));
         Ok(())
     }
 
@@ -2,6 +2,14 @@ pub use lambda_runtime_api_client::body::{sender::Sender, Body};
 
 pub use crate::types::StreamResponse as Response;
 
+use crate::Error;
+use http::{
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+    HeaderValue,
+};
+use serde::Serialize;
+use std::{fmt::Write, marker::PhantomData, time::Duration};
+
 /// Create a new `Body` stream with associated Sender half.
 ///
 /// Examples
@@ -33,3 +41,336 @@ pub use crate::types::StreamResponse as Response;
 pub fn channel() -> (Sender, Body) {
     Body::channel()
 }
+
+/// Create a new Server-Sent Events stream with associated [SseSender] half.
+///
+/// The returned response has its `Content-Type` set to `text/event-stream`.
+///
+/// Examples
+///
+/// ```
+/// use lambda_runtime::{
+///     streaming::{sse_channel, Body, Response, SseEvent},
+///     Error, LambdaEvent,
+/// };
+///
+/// async fn func(_event: LambdaEvent<serde_json::Value>) -> Result<Response<Body>, Error> {
+///     let (mut tx, response) = sse_channel();
+///
+///     tokio::spawn(async move {
+///         for token in ["Hello", " world", "!"] {
+///             tx.send(SseEvent::new(token).with_event("token")).await.unwrap();
+///         }
+///         tx.send(SseEvent::new("[DONE]")).await.unwrap();
+///     });
+///
+///     Ok(response)
+/// }
+/// ```
+pub fn sse_channel() -> (SseSender, Response<Body>) {
+    let (sender, body) = Body::channel();
+    let mut response = Response::from(body);
+    let headers = &mut response.metadata_prelude.headers;
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    (SseSender { sender }, response)
+}
+
+/// Create a new newline-delimited JSON stream with associated [NdjsonSender] half.
+///
+/// The returned response has its `Content-Type` set to `application/x-ndjson`.
+///
+/// Examples
+///
+/// ```
+/// use lambda_runtime::{
+///     streaming::{ndjson_channel, Body, Response},
+///     Error, LambdaEvent,
+/// };
+/// use serde_json::{json, Value};
+///
+/// async fn func(_event: LambdaEvent<Value>) -> Result<Response<Body>, Error> {
+///     let (mut tx, response) = ndjson_channel::<Value>();
+///
+///     tokio::spawn(async move {
+///         for id in 0..3 {
+///             tx.send(&json!({ "id": id })).await.unwrap();
+///         }
+///     });
+///
+///     Ok(response)
+/// }
+/// ```
+pub fn ndjson_channel<T>() -> (NdjsonSender<T>, Response<Body>)
+where
+    T: Serialize,
+{
+    let (sender, body) = Body::channel();
+    let mut response = Response::from(body);
+    response
+        .metadata_prelude
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"));
+    let sender = NdjsonSender {
+        sender,
+        _item: PhantomData,
+    };
+    (sender, response)
+}
+
+/// A single Server-Sent Event.
+///
+/// See the [HTML specification](https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation)
+/// for how clients interpret each field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl SseEvent {
+    /// Create a new event with the given data. Data with several lines is sent
+    /// as several `data` fields, which clients join back together.
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the event type, sent in the `event` field.
+    pub fn with_event(self, event: impl Into<String>) -> Self {
+        Self {
+            event: Some(event.into()),
+            ..self
+        }
+    }
+
+    /// Set the event id, sent in the `id` field.
+    pub fn with_id(self, id: impl Into<String>) -> Self {
+        Self {
+            id: Some(id.into()),
+            ..self
+        }
+    }
+
+    /// Set the reconnection time of the client, sent in the `retry` field.
+    pub fn with_retry(self, retry: Duration) -> Self {
+        Self {
+            retry: Some(retry),
+            ..self
+        }
+    }
+
+    /// Encode the event in the `text/event-stream` format.
+    fn encode(&self) -> String {
+        let mut buf = String::new();
+        if let Some(event) = &self.event {
+            let _ = writeln!(buf, "event: {}", single_line(event));
+        }
+        if let Some(id) = &self.id {
+            let _ = writeln!(buf, "id: {}", single_line(id));
+        }
+        if let Some(retry) = self.retry {
+            let _ = writeln!(buf, "retry: {}", retry.as_millis());
+        }
+        for line in lines(&self.data) {
+            let _ = writeln!(buf, "data: {line}");
+        }
+        buf.push('\n');
+        buf
+    }
+}
+
+/// Line breaks would end the field early and inject other fields into the stream.
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+/// Split a value on the `\r\n`, `\n` and `\r` line breaks, which all end a line
+/// in the `text/event-stream` format.
+fn lines(value: &str) -> impl Iterator<Item = &str> {
+    let mut rest = Some(value);
+    std::iter::from_fn(move || {
+        let value = rest?;
+        match value.find(['\r', '\n']) {
+            Some(end) => {
+                let next = if value[end..].starts_with("\r\n") {
+                    end + 2
+                } else {
+                    end + 1
+                };
+                rest = Some(&value[next..]);
+                Some(&value[..end])
+            }
+            None => {
+                rest = None;
+                Some(value)
+            }
+        }
+    })
+}
+
+/// Sender half of a Server-Sent Events stream, created with [sse_channel].
+#[must_use = "SseSender does nothing unless sent on"]
+pub struct SseSender {
+    sender: Sender,
+}
+
+impl SseSender {
+    /// Send an event to the client.
+    pub async fn send(&mut self, event: SseEvent) -> Result<(), Error> {
+        self.sender.send_data(event.encode().into()).await?;
+        Ok(())
+    }
+
+    /// Send a comment, which clients ignore.
+    pub async fn send_comment(&mut self, comment: &str) -> Result<(), Error> {
+        let mut buf = String::new();
+        for line in lines(comment) {
+            let _ = writeln!(buf, ": {line}");
+        }
+        buf.push('\n');
+        self.sender.send_data(buf.into()).await?;
+        Ok(())
+    }
+
+    /// Send a keep-alive comment, to prevent proxies and clients from closing
+    /// an idle connection while the function is working on the next event.
+    ///
+    /// The sender doesn't send keep-alive comments on its own, so schedule them
+    /// while you wait for the next event, for example with a [tokio::time::interval]:
+    ///
+    /// ```
+    /// use lambda_runtime::{
+    ///     streaming::{sse_channel, Body, Response, SseEvent},
+    ///     Error, LambdaEvent,
+    /// };
+    /// use std::time::Duration;
+    ///
+    /// async fn func(_event: LambdaEvent<serde_json::Value>) -> Result<Response<Body>, Error> {
+    ///     let (mut tx, response) = sse_channel();
+    ///
+    ///     tokio::spawn(async move {
+    ///         let mut keep_alive = tokio::time::interval(Duration::from_secs(15));
+    ///         let result = tokio::time::sleep(Duration::from_secs(60));
+    ///         tokio::pin!(result);
+    ///         loop {
+    ///             tokio::select! {
+    ///                 _ = keep_alive.tick() => tx.keep_alive().await.unwrap(),
+    ///                 _ = &mut result => break,
+    ///             }
+    ///         }
+    ///         tx.send(SseEvent::new("done")).await.unwrap();
+    ///     });
+    ///
+    ///     Ok(response)
+    /// }
+    /// ```
+    pub async fn keep_alive(&mut self) -> Result<(), Error> {
+        self.sender.send_data(":\n\n".into()).await?;
+        Ok(())
+    }
+
+    /// Terminate the stream with an error.
+    pub fn send_error(&mut self, err: Error) {
+        self.sender.send_error(lambda_runtime_api_client::Error::new(err))
+    }
+}
+
+/// Sender half of a newline-delimited JSON stream, created with [ndjson_channel].
+#[must_use = "NdjsonSender does nothing unless sent on"]
+pub struct NdjsonSender<T> {
+    sender: Sender,
+    _item: PhantomData<fn(&T)>,
+}
+
+impl<T> NdjsonSender<T>
+where
+    T: Serialize,
+{
+    /// Serialize an item as JSON and send it to the client, followed by a line break.
+    pub async fn send(&mut self, item: &T) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(item)?;
+        line.push(b'\n');
+        self.sender.send_data(line.into()).await?;
+        Ok(())
+    }
+
+    /// Terminate the stream with an error.
+    pub fn send_error(&mut self, err: Error) {
+        self.sender.send_error(lambda_runtime_api_client::Error::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn sse_event_encoding() {
+        let event = SseEvent::new("first\nsecond")
+            .with_event("token")
+            .with_id("42")
+            .with_retry(Duration::from_secs(3));
+        assert_eq!(
+            "event: token\nid: 42\nretry: 3000\ndata: first\ndata: second\n\n",
+            event.encode()
+        );
+    }
+
+    #[test]
+    fn sse_event_fields_are_single_line() {
+        let event = SseEvent::new("").with_event("a\r\nb");
+        assert_eq!("event: ab\ndata: \n\n", event.encode());
+    }
+
+    #[test]
+    fn sse_data_is_split_on_every_line_break() {
+        let event = SseEvent::new("a\rretry: 1\r\nb\nc");
+        assert_eq!("data: a\ndata: retry: 1\ndata: b\ndata: c\n\n", event.encode());
+    }
+
+    #[tokio::test]
+    async fn sse_comments_are_split_on_every_line_break() {
+        let (mut tx, response) = sse_channel();
+
+        tokio::spawn(async move {
+            tx.send_comment("a\rretry: 1").await.unwrap();
+        });
+
+        let body = response.stream.collect().await.unwrap().to_bytes();
+        assert_eq!(": a\n: retry: 1\n\n", body);
+    }
+
+    #[tokio::test]
+    async fn sse_channel_streams_events() {
+        let (mut tx, response) = sse_channel();
+        assert_eq!("text/event-stream", response.metadata_prelude.headers[CONTENT_TYPE]);
+
+        tokio::spawn(async move {
+            tx.keep_alive().await.unwrap();
+            tx.send(SseEvent::new("hello")).await.unwrap();
+        });
+
+        let body = response.stream.collect().await.unwrap().to_bytes();
+        assert_eq!(":\n\ndata: hello\n\n", body);
+    }
+
+    #[tokio::test]
+    async fn ndjson_channel_streams_lines() {
+        let (mut tx, response) = ndjson_channel::<Value>();
+        assert_eq!("application/x-ndjson", response.metadata_prelude.headers[CONTENT_TYPE]);
+
+        tokio::spawn(async move {
+            tx.send(&json!({"id": 1})).await.unwrap();
+            tx.send(&json!({"id": 2})).await.unwrap();
+        });
+
+        let body = response.stream.collect().await.unwrap().to_bytes();
+        assert_eq!("{\"id\":1}\n{\"id\":2}\n", body);
+    }
+}
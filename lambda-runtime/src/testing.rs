@@ -0,0 +1,646 @@
+//! This module provides an in-process fake of the [Lambda Runtime
+//! API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html) to test
+//! Lambda functions without deploying them.
+//!
+//! Queue events in a [FakeRuntimeApi], and run a [Runtime] created with
+//! [FakeRuntimeApi::runtime] until all the events have been processed.
+//! Every response, error and streamed body sent by the runtime is captured
+//! as a [TestInvocation] for assertions. Initialization and SnapStart restore
+//! errors are available with [FakeRuntimeApi::init_errors] and
+//! [FakeRuntimeApi::restore_errors].
+//!
+//! # Example
+//! ```
+//! use lambda_runtime::{
+//!     service_fn,
+//!     testing::{FakeRuntimeApi, TestEvent, TestOutcome},
+//!     Error, LambdaEvent,
+//! };
+//! use serde_json::{json, Value};
+//!
+//! async fn echo(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let api = FakeRuntimeApi::start().await?;
+//!     api.enqueue(TestEvent::json(&json!({ "command": "hello" }))?);
+//!
+//!     let invocations = api.run(api.runtime(service_fn(echo))).await?;
+//!     match &invocations[0].outcome {
+//!         TestOutcome::Response(body) => assert_eq!(&body[..], br#"{"command":"hello"}"#),
+//!         outcome => panic!("unexpected outcome: {outcome:?}"),
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::{
+    diagnostic::XRayErrorCause,
+    layers::{CatchPanicService, RuntimeApiClientService, RuntimeApiResponseService},
+    types::{ClientContext, CognitoIdentity},
+    Config, Diagnostic, Error, IntoFunctionResponse, LambdaEvent, LambdaInvocation, MetadataPrelude, Runtime,
+};
+use bytes::Bytes;
+use http::{
+    header::{HeaderName, CONTENT_TYPE},
+    HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri,
+};
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::rt::tokio::TokioIo;
+use lambda_runtime_api_client::{api::runtime::NextInvocation, BoxError, Client};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    fmt::Debug,
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::{net::TcpListener, sync::Notify, task::JoinHandle};
+use tokio_stream::Stream;
+use tower::Service;
+use tracing::{error, trace};
+
+const INVOCATION_PREFIX: &str = "/2018-06-01/runtime/invocation/";
+const INIT_ERROR_PATH: &str = "/2018-06-01/runtime/init/error";
+const RESTORE_NEXT_PATH: &str = "/2018-06-01/runtime/restore/next";
+const RESTORE_ERROR_PATH: &str = "/2018-06-01/runtime/restore/error";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+const DEFAULT_FUNCTION_ARN: &str = "arn:aws:lambda:us-east-1:123456789012:function:test-function";
+
+/// An event to send to the function through the [FakeRuntimeApi].
+#[derive(Debug, Clone)]
+pub struct TestEvent {
+    body: Bytes,
+    request_id: Option<String>,
+    deadline: Option<SystemTime>,
+    invocation: NextInvocation,
+    headers: HeaderMap,
+}
+
+impl TestEvent {
+    /// Create a new event with a raw payload.
+    pub fn new(body: impl Into<Bytes>) -> Self {
+        TestEvent {
+            body: body.into(),
+            request_id: None,
+            deadline: None,
+            invocation: NextInvocation {
+                invoked_function_arn: Some(DEFAULT_FUNCTION_ARN.to_string()),
+                ..Default::default()
+            },
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Create a new event with a payload serialized as JSON.
+    pub fn json<T: Serialize>(payload: &T) -> Result<Self, Error> {
+        Ok(Self::new(serde_json::to_vec(payload)?))
+    }
+
+    /// Set the request id of the invocation. By default, a unique id is generated for every event.
+    pub fn with_request_id(self, request_id: impl Into<String>) -> Self {
+        TestEvent {
+            request_id: Some(request_id.into()),
+            ..self
+        }
+    }
+
+    /// Set the deadline of the invocation. By default, the deadline is three seconds after
+    /// the runtime receives the event, like the default timeout of a Lambda function.
+    pub fn with_deadline(self, deadline: SystemTime) -> Self {
+        TestEvent {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Set the ARN of the invoked function.
+    pub fn with_invoked_function_arn(mut self, arn: &str) -> Result<Self, Error> {
+        HeaderValue::from_str(arn)?;
+        self.invocation.invoked_function_arn = Some(arn.to_string());
+        Ok(self)
+    }
+
+    /// Set the X-Ray trace id of the invocation.
+    pub fn with_xray_trace_id(mut self, trace_id: &str) -> Result<Self, Error> {
+        HeaderValue::from_str(trace_id)?;
+        self.invocation.trace_id = Some(trace_id.to_string());
+        Ok(self)
+    }
+
+    /// Set the client context sent by the AWS Mobile SDK.
+    pub fn with_client_context(mut self, client_context: &ClientContext) -> Result<Self, Error> {
+        self.invocation.client_context = Some(serde_json::to_string(client_context)?);
+        Ok(self)
+    }
+
+    /// Set the Cognito identity that invoked the function.
+    pub fn with_identity(mut self, identity: &CognitoIdentity) -> Result<Self, Error> {
+        self.invocation.cognito_identity = Some(serde_json::to_string(identity)?);
+        Ok(self)
+    }
+
+    /// Add a custom header to the response of the `/invocation/next` request.
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self, Error> {
+        self.headers
+            .insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+        Ok(self)
+    }
+}
+
+/// The result of an invocation, as received by the [FakeRuntimeApi].
+#[derive(Debug)]
+pub struct TestInvocation {
+    /// The request id of the invocation.
+    pub request_id: String,
+    /// What the runtime sent back for the invocation.
+    pub outcome: TestOutcome,
+}
+
+/// What the runtime sent back to the [FakeRuntimeApi] for an invocation.
+#[derive(Debug)]
+pub enum TestOutcome {
+    /// A buffered response, with its serialized body.
+    Response(Bytes),
+    /// A streaming response.
+    StreamingResponse {
+        /// The metadata prelude sent before the body.
+        metadata_prelude: MetadataPrelude,
+        /// The body sent after the metadata prelude.
+        body: Bytes,
+        /// The trailers sent at the end of the stream, if any.
+        trailers: Option<HeaderMap>,
+    },
+    /// An invocation error.
+    Error {
+        /// The error reported by the runtime.
+        diagnostic: Diagnostic<'static>,
+        /// The X-Ray error cause reported by the runtime, if any.
+        xray_error_cause: Option<XRayErrorCause>,
+    },
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<TestEvent>,
+    pending: usize,
+    next_request_id: usize,
+    invocations: Vec<TestInvocation>,
+    init_errors: Vec<Diagnostic<'static>>,
+    restore_errors: Vec<Diagnostic<'static>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    events: Notify,
+    completions: Notify,
+}
+
+/// In-process fake of the Lambda Runtime API.
+///
+/// The fake server listens on a random local port, serves the queued events to the runtime,
+/// and records what the runtime sends back. It stops when it's dropped.
+pub struct FakeRuntimeApi {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    server: JoinHandle<()>,
+}
+
+impl FakeRuntimeApi {
+    /// Start a new fake Runtime API on a random local port.
+    pub async fn start() -> Result<Self, Error> {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Shared::default());
+
+        let server_shared = shared.clone();
+        let server = tokio::spawn(async move {
+            loop {
+                let (tcp, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(err) => {
+                        error!(error = ?err, "fake Runtime API failed to accept connection");
+                        continue;
+                    }
+                };
+                let shared = server_shared.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| handle(shared.clone(), req));
+                    if let Err(err) = http1::Builder::new().serve_connection(TokioIo::new(tcp), service).await {
+                        trace!(error = ?err, "fake Runtime API connection closed");
+                    }
+                });
+            }
+        });
+
+        Ok(FakeRuntimeApi { addr, shared, server })
+    }
+
+    /// The base URI of the fake Runtime API.
+    pub fn endpoint(&self) -> Uri {
+        format!("http://{}", self.addr)
+            .parse()
+            .expect("socket address is a valid URI")
+    }
+
+    /// Create a client that sends requests to the fake Runtime API.
+    pub fn client(&self) -> Result<Client, Error> {
+        Ok(Client::builder().with_endpoint(self.endpoint()).build()?)
+    }
+
+    /// Queue an event to send to the function.
+    pub fn enqueue(&self, event: TestEvent) {
+        let mut state = self.shared.state.lock().expect("fake Runtime API state poisoned");
+        state.queue.push_back(event);
+        state.pending += 1;
+        drop(state);
+        self.shared.events.notify_waiters();
+    }
+
+    /// Initialization errors reported by the runtime.
+    pub fn init_errors(&self) -> Vec<Diagnostic<'static>> {
+        let state = self.shared.state.lock().expect("fake Runtime API state poisoned");
+        state.init_errors.clone()
+    }
+
+    /// Errors reported by the runtime while a SnapStart snapshot was restored.
+    pub fn restore_errors(&self) -> Vec<Diagnostic<'static>> {
+        let state = self.shared.state.lock().expect("fake Runtime API state poisoned");
+        state.restore_errors.clone()
+    }
+
+    /// Take the invocations that completed since the last call, for runtimes that are
+    /// driven without [FakeRuntimeApi::run].
+    pub fn take_invocations(&self) -> Vec<TestInvocation> {
+        let mut state = self.shared.state.lock().expect("fake Runtime API state poisoned");
+        std::mem::take(&mut state.invocations)
+    }
+
+    /// Create a runtime that executes the provided handler for the events of this fake
+    /// Runtime API.
+    ///
+    /// The runtime uses a test configuration, with `test-function` as the function name.
+    /// Like [Runtime::new], it doesn't add any middleware to the handler, so you can add
+    /// your own with [Runtime::layer].
+    #[allow(clippy::type_complexity)]
+    pub fn runtime<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>(
+        &self,
+        handler: F,
+    ) -> Runtime<
+        RuntimeApiClientService<
+            RuntimeApiResponseService<
+                CatchPanicService<'a, F>,
+                EventPayload,
+                Response,
+                BufferedResponse,
+                StreamingResponse,
+                StreamItem,
+                StreamError,
+            >,
+        >,
+    >
+    where
+        F: Service<LambdaEvent<EventPayload>, Response = Response>,
+        F::Future: Future<Output = Result<Response, F::Error>>,
+        F::Error: Into<Diagnostic<'a>> + Debug,
+        EventPayload: for<'de> Deserialize<'de>,
+        Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
+        BufferedResponse: Serialize,
+        StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
+        StreamItem: Into<Bytes> + Send,
+        StreamError: Into<Error> + Send + Debug,
+    {
+        let config = Config::builder()
+            .with_function_name("test-function")
+            .with_memory(128)
+            .with_version("$LATEST")
+            .with_log_stream("test-stream")
+            .with_log_group("/aws/lambda/test-function")
+            .build();
+        let client = self.client().expect("Unable to create a runtime client");
+        Runtime::with_client(handler, config, client)
+    }
+
+    /// Run the runtime until all the queued events have been processed, and return
+    /// the invocations that completed in the meantime.
+    pub async fn run<S>(&self, runtime: Runtime<S>) -> Result<Vec<TestInvocation>, Error>
+    where
+        S: Service<LambdaInvocation, Response = (), Error = BoxError>,
+    {
+        tokio::select! {
+            result = runtime.run() => result?,
+            _ = self.drained() => {},
+        }
+
+        Ok(self.take_invocations())
+    }
+
+    async fn drained(&self) {
+        loop {
+            let completed = self.shared.completions.notified();
+            {
+                let state = self.shared.state.lock().expect("fake Runtime API state poisoned");
+                if state.pending == 0 {
+                    return;
+                }
+            }
+            completed.await;
+        }
+    }
+}
+
+impl Drop for FakeRuntimeApi {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn handle(shared: Arc<Shared>, req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let response = match (&method, path.as_str()) {
+        (&Method::GET, "/2018-06-01/runtime/invocation/next") => next_event(&shared).await,
+        (&Method::POST, INIT_ERROR_PATH) => record_error(&shared, req, |state| &mut state.init_errors).await,
+        (&Method::GET, RESTORE_NEXT_PATH) => empty_response(StatusCode::OK),
+        (&Method::POST, RESTORE_ERROR_PATH) => record_error(&shared, req, |state| &mut state.restore_errors).await,
+        (&Method::POST, path) => match path.strip_prefix(INVOCATION_PREFIX).and_then(|p| p.rsplit_once('/')) {
+            Some((request_id, "response")) => {
+                let request_id = request_id.to_string();
+                complete(&shared, request_id, response_outcome(req).await)
+            }
+            Some((request_id, "error")) => {
+                let request_id = request_id.to_string();
+                complete(&shared, request_id, error_outcome(req).await)
+            }
+            _ => empty_response(StatusCode::NOT_FOUND),
+        },
+        _ => empty_response(StatusCode::NOT_FOUND),
+    };
+    Ok(response)
+}
+
+async fn record_error(
+    shared: &Shared,
+    req: Request<Incoming>,
+    errors: impl FnOnce(&mut State) -> &mut Vec<Diagnostic<'static>>,
+) -> Response<Full<Bytes>> {
+    let diagnostic = match req.into_body().collect().await {
+        Ok(body) => parse_diagnostic(&body.to_bytes()),
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+    let mut state = shared.state.lock().expect("fake Runtime API state poisoned");
+    errors(&mut state).push(diagnostic);
+    empty_response(StatusCode::ACCEPTED)
+}
+
+async fn next_event(shared: &Shared) -> Response<Full<Bytes>> {
+    let (request_id, event) = loop {
+        let queued = shared.events.notified();
+        {
+            let mut state = shared.state.lock().expect("fake Runtime API state poisoned");
+            if let Some(event) = state.queue.pop_front() {
+                state.next_request_id += 1;
+                let request_id = event
+                    .request_id
+                    .clone()
+                    .unwrap_or_else(|| format!("test-request-{}", state.next_request_id));
+                break (request_id, event);
+            }
+        }
+        queued.await;
+    };
+
+    let deadline = event
+        .deadline
+        .unwrap_or_else(|| SystemTime::now() + DEFAULT_TIMEOUT)
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let invocation = NextInvocation {
+        request_id,
+        deadline_ms: deadline.as_millis() as u64,
+        ..event.invocation
+    };
+    let mut headers = match invocation.to_headers() {
+        Ok(headers) => headers,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    };
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.extend(event.headers);
+
+    let mut response = Response::new(Full::new(event.body));
+    *response.headers_mut() = headers;
+    response
+}
+
+async fn response_outcome(req: Request<Incoming>) -> Result<TestOutcome, BoxError> {
+    let streaming = req
+        .headers()
+        .get("lambda-runtime-function-response-mode")
+        .map(|mode| mode == "streaming")
+        .unwrap_or_default();
+
+    let collected = req.into_body().collect().await?;
+    let trailers = collected.trailers().cloned();
+    let body = collected.to_bytes();
+    if !streaming {
+        return Ok(TestOutcome::Response(body));
+    }
+
+    let separator = body
+        .windows(8)
+        .position(|window| window == [0u8; 8])
+        .ok_or("missing metadata prelude separator in streaming response")?;
+    let metadata_prelude = serde_json::from_slice(&body[..separator])?;
+    Ok(TestOutcome::StreamingResponse {
+        metadata_prelude,
+        body: body.slice(separator + 8..),
+        trailers,
+    })
+}
+
+async fn error_outcome(req: Request<Incoming>) -> Result<TestOutcome, BoxError> {
+    let xray_error_cause = match req.headers().get("lambda-runtime-function-xray-error-cause") {
+        Some(value) => Some(serde_json::from_slice(value.as_bytes())?),
+        None => None,
+    };
+    let body = req.into_body().collect().await?.to_bytes();
+    Ok(TestOutcome::Error {
+        diagnostic: parse_diagnostic(&body),
+        xray_error_cause,
+    })
+}
+
+fn complete(shared: &Shared, request_id: String, outcome: Result<TestOutcome, BoxError>) -> Response<Full<Bytes>> {
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+
+    let mut state = shared.state.lock().expect("fake Runtime API state poisoned");
+    state.invocations.push(TestInvocation { request_id, outcome });
+    state.pending = state.pending.saturating_sub(1);
+    drop(state);
+
+    shared.completions.notify_waiters();
+    empty_response(StatusCode::ACCEPTED)
+}
+
+fn parse_diagnostic(body: &[u8]) -> Diagnostic<'static> {
+    serde_json::from_slice(body).unwrap_or_else(|_| Diagnostic {
+        error_type: "Unknown".into(),
+        error_message: String::from_utf8_lossy(body).into_owned().into(),
+        ..Default::default()
+    })
+}
+
+fn empty_response(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = status;
+    response
+}
+
+fn error_response(status: StatusCode, err: impl ToString) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(err.to_string())));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, streaming::Response as StreamResponse};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn test_buffered_responses_and_errors() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::json(&json!({ "name": "hello" }))?.with_request_id("ok"));
+        api.enqueue(
+            TestEvent::json(&json!({}))?
+                .with_request_id("err")
+                .with_xray_trace_id("Root=1-5759e988-bd862e3fe1be46a994272793")?,
+        );
+
+        async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+            match event.payload.get("name") {
+                Some(name) => Ok(json!({ "greeting": name })),
+                None => Err("missing name".into()),
+            }
+        }
+
+        let invocations = api.run(api.runtime(service_fn(func))).await?;
+        assert_eq!(invocations.len(), 2);
+
+        let ok = invocations.iter().find(|i| i.request_id == "ok").unwrap();
+        match &ok.outcome {
+            TestOutcome::Response(body) => assert_eq!(&body[..], br#"{"greeting":"hello"}"#),
+            outcome => panic!("unexpected outcome: {outcome:?}"),
+        }
+
+        let err = invocations.iter().find(|i| i.request_id == "err").unwrap();
+        match &err.outcome {
+            TestOutcome::Error {
+                diagnostic,
+                xray_error_cause,
+            } => {
+                assert_eq!(diagnostic.error_message, "missing name");
+                assert!(xray_error_cause.is_some());
+            }
+            outcome => panic!("unexpected outcome: {outcome:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_context_headers() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        let deadline = SystemTime::UNIX_EPOCH + Duration::from_millis(1542409706888);
+        api.enqueue(
+            TestEvent::new("{}")
+                .with_request_id("ctx")
+                .with_deadline(deadline)
+                .with_invoked_function_arn("arn:aws:lambda:us-west-2:123456789012:function:my-function")?,
+        );
+
+        async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+            Ok(json!({
+                "deadline": event.context.deadline,
+                "arn": event.context.invoked_function_arn,
+            }))
+        }
+
+        let invocations = api.run(api.runtime(service_fn(func))).await?;
+        match &invocations[0].outcome {
+            TestOutcome::Response(body) => {
+                let body: Value = serde_json::from_slice(body)?;
+                assert_eq!(body["deadline"], 1542409706888u64);
+                assert_eq!(
+                    body["arn"],
+                    "arn:aws:lambda:us-west-2:123456789012:function:my-function"
+                );
+            }
+            outcome => panic!("unexpected outcome: {outcome:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_streaming_response() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::new("{}"));
+
+        async fn func(_event: LambdaEvent<Value>) -> Result<StreamResponse<crate::streaming::Body>, Error> {
+            let (mut tx, rx) = crate::streaming::channel();
+            tokio::spawn(async move {
+                tx.send_data("hello ".into()).await.unwrap();
+                tx.send_data("world".into()).await.unwrap();
+            });
+            Ok(StreamResponse::from(rx))
+        }
+
+        let invocations = api.run(api.runtime(service_fn(func))).await?;
+        match &invocations[0].outcome {
+            TestOutcome::StreamingResponse {
+                metadata_prelude, body, ..
+            } => {
+                assert_eq!(metadata_prelude.status_code, StatusCode::OK);
+                assert_eq!(&body[..], b"hello world");
+            }
+            outcome => panic!("unexpected outcome: {outcome:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_streaming_io_error() -> Result<(), Error> {
+        let api = FakeRuntimeApi::start().await?;
+        api.enqueue(TestEvent::new("{}"));
+
+        async fn func(
+            _event: LambdaEvent<Value>,
+        ) -> Result<StreamResponse<impl Stream<Item = Result<Bytes, std::io::Error>>>, Error> {
+            let chunks = vec![
+                Ok(Bytes::from("partial")),
+                Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe)),
+            ];
+            Ok(StreamResponse::from(tokio_stream::iter(chunks)))
+        }
+
+        let invocations = api.run(api.runtime(service_fn(func))).await?;
+        match &invocations[0].outcome {
+            TestOutcome::StreamingResponse { body, .. } => {
+                let body = String::from_utf8_lossy(body);
+                assert!(body.starts_with("partial"));
+                assert!(body.contains("Lambda-Runtime-Function-Error-Type: Runtime.StreamError\r\n"));
+            }
+            outcome => panic!("unexpected outcome: {outcome:?}"),
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
-use crate::{Error, RefConfig};
+use crate::{Diagnostic, Error, RefConfig};
 use base64::prelude::*;
 use bytes::Bytes;
-use http::{header::ToStrError, HeaderMap, HeaderValue, StatusCode};
-use lambda_runtime_api_client::body::Body;
+use http::{HeaderMap, HeaderValue, StatusCode};
+use lambda_runtime_api_client::{
+    api::runtime::{NextInvocation, REQUEST_ID_HEADER},
+    body::Body,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -103,41 +106,34 @@ impl Context {
     /// Create a new [Context] struct based on the function configuration
     /// and the incoming request data.
     pub fn new(request_id: &str, env_config: RefConfig, headers: &HeaderMap) -> Result<Self, Error> {
-        let client_context: Option<ClientContext> = if let Some(value) = headers.get("lambda-runtime-client-context") {
-            serde_json::from_str(value.to_str()?)?
-        } else {
-            None
-        };
+        let mut headers = headers.clone();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(request_id)?);
+        Self::from_invocation(NextInvocation::from_headers(&headers)?, env_config)
+    }
 
-        let identity: Option<CognitoIdentity> = if let Some(value) = headers.get("lambda-runtime-cognito-identity") {
-            serde_json::from_str(value.to_str()?)?
-        } else {
-            None
+    /// Create a new [Context] struct based on the function configuration
+    /// and the invocation returned by the Runtime API.
+    pub fn from_invocation(invocation: NextInvocation, env_config: RefConfig) -> Result<Self, Error> {
+        let client_context = match invocation.client_context {
+            Some(value) => Some(serde_json::from_str(&value)?),
+            None => None,
+        };
+        let identity = match invocation.cognito_identity {
+            Some(value) => Some(serde_json::from_str(&value)?),
+            None => None,
         };
 
-        let ctx = Context {
-            request_id: request_id.to_owned(),
-            deadline: headers
-                .get("lambda-runtime-deadline-ms")
-                .expect("missing lambda-runtime-deadline-ms header")
-                .to_str()?
-                .parse::<u64>()?,
-            invoked_function_arn: headers
-                .get("lambda-runtime-invoked-function-arn")
-                .unwrap_or(&HeaderValue::from_static(
-                    "No header lambda-runtime-invoked-function-arn found.",
-                ))
-                .to_str()?
-                .to_owned(),
-            xray_trace_id: headers
-                .get("lambda-runtime-trace-id")
-                .map(|v| String::from_utf8_lossy(v.as_bytes()).to_string()),
+        Ok(Context {
+            request_id: invocation.request_id,
+            deadline: invocation.deadline_ms,
+            invoked_function_arn: invocation
+                .invoked_function_arn
+                .unwrap_or_else(|| "No header lambda-runtime-invoked-function-arn found.".to_owned()),
+            xray_trace_id: invocation.trace_id,
             client_context,
             identity,
             env_config,
-        };
-
-        Ok(ctx)
+        })
     }
 
     /// The execution deadline for the current invocation.
@@ -147,11 +143,12 @@ impl Context {
 }
 
 /// Extract the invocation request id from the incoming request.
-pub(crate) fn invoke_request_id(headers: &HeaderMap) -> Result<&str, ToStrError> {
-    headers
+pub(crate) fn invoke_request_id(headers: &HeaderMap) -> Result<&str, Error> {
+    let request_id = headers
         .get("lambda-runtime-aws-request-id")
-        .expect("missing lambda-runtime-aws-request-id header")
-        .to_str()
+        .ok_or("missing lambda-runtime-aws-request-id header")?
+        .to_str()?;
+    Ok(request_id)
 }
 
 /// Incoming Lambda request containing the event payload and context.
@@ -176,7 +173,7 @@ impl<T> LambdaEvent<T> {
 }
 
 /// Metadata prelude for a stream response.
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MetadataPrelude {
     #[serde(with = "http_serde::status_code")]
@@ -190,21 +187,30 @@ pub struct MetadataPrelude {
 }
 
 pub trait ToStreamErrorTrailer {
-    /// Convert the hyper error into a stream error trailer.
+    /// Convert the stream error into a stream error trailer.
     fn to_tailer(&self) -> String;
 }
 
-impl ToStreamErrorTrailer for Error {
+impl<'a> ToStreamErrorTrailer for Diagnostic<'a> {
     fn to_tailer(&self) -> String {
+        // The error type is sent as a header value, so it must fit on a single line.
+        let error_type = self.error_type.replace(['\r', '\n'], " ");
+        let error_body = serde_json::to_vec(self).unwrap_or_else(|_| self.error_message.as_bytes().to_vec());
         format!(
-            "Lambda-Runtime-Function-Error-Type: Runtime.StreamError\r\nLambda-Runtime-Function-Error-Body: {}\r\n",
-            BASE64_STANDARD.encode(self.to_string())
+            "Lambda-Runtime-Function-Error-Type: {}\r\nLambda-Runtime-Function-Error-Body: {}\r\n",
+            error_type,
+            BASE64_STANDARD.encode(error_body)
         )
     }
 }
 
 /// A streaming response that contains the metadata prelude and the stream of bytes that will be
 /// sent to the client.
+///
+/// When the stream fails, the error is sent to the client in the error trailers of the
+/// response, with the `Runtime.StreamError` error type. To report a specific error type,
+/// fail the stream with a [Diagnostic] converted into a boxed error, for example
+/// `Err(Diagnostic::new("UpstreamTimeout", "model stopped responding").into())`.
 #[derive(Debug)]
 pub struct StreamResponse<S> {
     ///  The metadata prelude.
@@ -226,6 +232,22 @@ pub enum FunctionResponse<B, S> {
     StreamingResponse(StreamResponse<S>),
 }
 
+/// What the runtime does with a buffered response that is larger than the
+/// [maximum payload size](https://docs.aws.amazon.com/lambda/latest/dg/gettingstarted-limits.html)
+/// of synchronous invocations, 6 MB.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ResponseSizePolicy {
+    /// Report a `Function.ResponseSizeTooLarge` error to the Lambda Runtime API instead of the response.
+    #[default]
+    Error,
+    /// Send the response in streaming mode, which accepts larger payloads.
+    ///
+    /// Only use this policy if the function is invoked with response streaming,
+    /// Lambda still rejects large responses of buffered invocations.
+    Stream,
+}
+
 /// a trait that can be implemented for any type that can be converted into a FunctionResponse.
 /// This allows us to use the `into` method to convert a type into a FunctionResponse.
 pub trait IntoFunctionResponse<B, S> {
@@ -304,6 +326,10 @@ mod test {
         headers.insert("lambda-runtime-deadline-ms", HeaderValue::from_static("123"));
         let tried = Context::new("id", config, &headers);
         assert!(tried.is_ok());
+        assert_eq!(
+            "No header lambda-runtime-invoked-function-arn found.",
+            tried.unwrap().invoked_function_arn
+        );
     }
 
     #[test]
@@ -437,8 +463,7 @@ mod test {
     }
 
     #[test]
-    #[should_panic]
-    fn context_with_missing_deadline_should_panic() {
+    fn context_with_missing_deadline_should_fail() {
         let config = Arc::new(Config::default());
 
         let mut headers = HeaderMap::new();
@@ -448,7 +473,8 @@ mod test {
             HeaderValue::from_static("arn::myarn"),
         );
         headers.insert("lambda-runtime-trace-id", HeaderValue::from_static("arn::myarn"));
-        let _ = Context::new("id", config, &headers);
+        let tried = Context::new("id", config, &headers);
+        assert!(tried.is_err());
     }
 
     #[test]
@@ -462,12 +488,11 @@ mod test {
         );
         headers.insert("lambda-runtime-trace-id", HeaderValue::from_static("arn::myarn"));
 
-        let _ = invoke_request_id(&headers);
+        assert_eq!("my-id", invoke_request_id(&headers).unwrap());
     }
 
     #[test]
-    #[should_panic]
-    fn invoke_request_id_should_panic() {
+    fn invoke_request_id_should_fail() {
         let mut headers = HeaderMap::new();
         headers.insert("lambda-runtime-deadline-ms", HeaderValue::from_static("123"));
         headers.insert(
@@ -476,6 +501,31 @@ mod test {
         );
         headers.insert("lambda-runtime-trace-id", HeaderValue::from_static("arn::myarn"));
 
-        let _ = invoke_request_id(&headers);
+        assert!(invoke_request_id(&headers).is_err());
+    }
+
+    #[test]
+    fn stream_error_trailer_carries_diagnostic() {
+        let diagnostic = Diagnostic {
+            error_type: "UpstreamTimeout".into(),
+            error_message: "model stopped\nresponding".into(),
+            ..Default::default()
+        };
+
+        let trailer = diagnostic.to_tailer();
+        let mut lines = trailer.split("\r\n");
+        assert_eq!(
+            "Lambda-Runtime-Function-Error-Type: UpstreamTimeout",
+            lines.next().unwrap()
+        );
+
+        let body = lines
+            .next()
+            .unwrap()
+            .strip_prefix("Lambda-Runtime-Function-Error-Body: ")
+            .unwrap();
+        let body = BASE64_STANDARD.decode(body).unwrap();
+        let decoded: Diagnostic<'_> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(diagnostic, decoded);
     }
 }